@@ -1,121 +1,422 @@
-use std::{cell::UnsafeCell, sync::Arc};
+use std::{
+    sync::{Arc, Weak},
+    time::{Duration, Instant, SystemTime},
+};
 
-use futures_util::{SinkExt, StreamExt};
-use tokio::{net::TcpStream, sync::broadcast};
+use aionbot_core::{connection::DisconnectReason, message::Message as AionMessage};
+use anyhow::{anyhow, Result};
+use futures_util::{
+    future::BoxFuture,
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, Mutex, Notify},
+    time::{interval, MissedTickBehavior},
+};
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 
 use crate::{
-    event::OnebotEvent,
-    models::{Action, ActionParams, MessageEvent, MinimalEvent},
+    event::{OnebotEvent, OnebotPayload},
+    models::{Action, MessageEvent, MetaEvent, MinimalEvent},
+    outbox::{Outbox, OutboxError, Target, Transport},
+    ws::{Config, HeartbeatConfig, Onebot},
 };
 
+pub(crate) type WsSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+pub(crate) type WsStream = SplitStream<WebSocketStream<TcpStream>>;
+
+/// Health status of a connected bot.
+#[derive(Clone, Debug)]
+pub struct BotStatus {
+    pub self_id: String,
+    /// Whether the bot account is online as reported by the implementation.
+    pub online: bool,
+    /// Whether the implementation reports itself in good status.
+    pub good: bool,
+    /// Whether heartbeats are arriving in time.
+    pub healthy: bool,
+    /// Time when the last frame was received from the bot.
+    pub last_seen: Option<SystemTime>,
+    /// Heartbeat interval announced by the implementation.
+    pub interval: Option<Duration>,
+}
+
 #[derive(Debug)]
-pub struct BotInstance {
-    pub id: String,
-    pub ws_stream: Option<WebSocketStream<TcpStream>>,
-    sender: broadcast::Sender<Box<OnebotEvent>>,
+struct Health {
+    online: bool,
+    good: bool,
+    healthy: bool,
+    last_seen: Option<SystemTime>,
+    last_heartbeat: Option<Instant>,
+    interval: Option<Duration>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            online: true,
+            good: true,
+            healthy: true,
+            last_seen: None,
+            last_heartbeat: None,
+            interval: None,
+        }
+    }
+}
+
+impl Health {
+    /// Check heartbeats at `now`, returns `false` if the connection should be
+    /// closed.
+    fn check(&mut self, id: &str, config: &HeartbeatConfig, now: Instant) -> bool {
+        let (Some(last_heartbeat), Some(interval)) = (self.last_heartbeat, self.interval) else {
+            return true;
+        };
+        let elapsed = now.saturating_duration_since(last_heartbeat);
+        if elapsed > interval.saturating_mul(config.close_after) {
+            log::error!(
+                "Bot {} missed heartbeats for {:?}, closing connection.",
+                id,
+                elapsed
+            );
+            return false;
+        }
+        if self.healthy && elapsed > interval.saturating_mul(config.unhealthy_after) {
+            log::warn!(
+                "Bot {} missed heartbeats for {:?}, marked as unhealthy.",
+                id,
+                elapsed
+            );
+            self.healthy = false;
+        }
+        true
+    }
+
+    fn update(&mut self, id: &str, meta_event: &MetaEvent) {
+        if meta_event.is_heartbeat() {
+            if !self.healthy {
+                log::info!("Bot {} heartbeat recovered.", id);
+            }
+            self.healthy = true;
+            self.last_heartbeat = Some(Instant::now());
+            // An interval of 0 would close the connection immediately.
+            self.interval = meta_event
+                .interval
+                .filter(|&interval| interval > 0)
+                .map(Duration::from_millis);
+            if let Some(status) = &meta_event.status {
+                self.online = status.online.unwrap_or(self.online);
+                self.good = status.good;
+            }
+        } else if meta_event.is_lifecycle() {
+            match meta_event.sub_type.as_deref() {
+                Some("enable") | Some("connect") => self.online = true,
+                Some("disable") => self.online = false,
+                _ => {}
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Bot {
-    inner: UnsafeCell<BotInstance>,
+    id: String,
+    sink: Mutex<WsSink>,
+    sender: broadcast::Sender<OnebotPayload>,
+    onebot: Weak<Onebot>,
+    shutdown: Notify,
+    shutdown_reason: std::sync::Mutex<Option<DisconnectReason>>,
+    health: std::sync::Mutex<Health>,
+    outbox: Arc<Outbox>,
+    config: Arc<Config>,
 }
 
-unsafe impl Send for Bot {}
-unsafe impl Sync for Bot {}
-
 impl Bot {
-    pub fn new(sender: broadcast::Sender<Box<OnebotEvent>>) -> Arc<Self> {
-        Arc::new(Self {
-            inner: UnsafeCell::new(BotInstance {
-                id: String::new(),
-                ws_stream: None,
-                sender,
-            }),
+    pub(crate) fn new(
+        id: String,
+        sink: WsSink,
+        sender: broadcast::Sender<OnebotPayload>,
+        onebot: Weak<Onebot>,
+        config: Arc<Config>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|bot: &Weak<Self>| Self {
+            id,
+            sink: Mutex::new(sink),
+            sender,
+            onebot,
+            shutdown: Notify::new(),
+            shutdown_reason: Default::default(),
+            health: Default::default(),
+            outbox: Outbox::new(bot.clone(), config.outbox.clone()),
+            config,
         })
     }
 
+    /// Get the self ID of the bot account.
     pub fn id(&self) -> &str {
-        unsafe { &(*self.inner.get()).id }
-    }
-
-    pub fn set_id(&self, id: String) {
-        unsafe { (*self.inner.get()).id = id }
-    }
-    pub fn set_ws_stream(&self, ws_stream: WebSocketStream<TcpStream>) {
-        unsafe { (*self.inner.get()).ws_stream = Some(ws_stream) }
-    }
-
-    pub async fn listen(self: Arc<Self>) {
-        let bot = unsafe { &mut (*self.inner.get()) };
-        if let Some(ws_stream) = &mut bot.ws_stream {
-            log::info!("Starting listening for messages from bot {}...", bot.id);
-            ws_stream
-                .for_each(|message| async {
-                    if let Ok(Message::Text(message)) = message {
-                        log::debug!("Received event message: {}", message);
-                        match serde_json::from_str::<MinimalEvent>(&message) {
-                            Ok(data) => {
-                                if !data.is_message() {
-                                    log::debug!(
-                                        "Received non-message event: {}, ignored.",
-                                        message
-                                    );
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                log::warn!("Error deserializing event minimally: {}", e);
-                                return;
-                            }
-                        };
-                        let message_event: MessageEvent = match serde_json::from_str(&message) {
-                            Ok(data) => data,
-                            Err(e) => {
-                                log::error!("Error deserializing message: {}", e);
-                                return;
-                            }
-                        };
-                        let event = OnebotEvent {
-                            plain_data: message_event,
-                            bot: self.clone(),
-                        };
-                        if let Err(e) = bot.sender.send(Box::new(event)) {
-                            log::warn!("Error sending event: {}", e);
-                        }
-                    } else {
-                        log::warn!("Received non-text message: {:?}", message)
+        &self.id
+    }
+
+    /// Get the names the bot responds to.
+    pub fn nicknames(&self) -> &[String] {
+        &self.config.nicknames
+    }
+
+    /// Get the OneBot server this bot is connected to.
+    pub fn onebot(&self) -> Option<Arc<Onebot>> {
+        self.onebot.upgrade()
+    }
+
+    pub fn status(&self) -> BotStatus {
+        let health = self.health.lock().unwrap();
+        BotStatus {
+            self_id: self.id.clone(),
+            online: health.online,
+            good: health.good,
+            healthy: health.healthy,
+            last_seen: health.last_seen,
+            interval: health.interval,
+        }
+    }
+
+    /// Check heartbeats, returns `false` if the connection should be closed.
+    fn check_health(&self) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .check(&self.id, &self.config.heartbeat, Instant::now())
+    }
+
+    fn handle_meta_event(&self, message: &str) {
+        let meta_event: MetaEvent = match serde_json::from_str(message) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("Error deserializing meta event: {}", e);
+                return;
+            }
+        };
+        self.health.lock().unwrap().update(&self.id, &meta_event);
+    }
+
+    /// Listen for events until the connection is closed.
+    pub(crate) async fn listen(self: Arc<Self>, mut stream: WsStream) -> DisconnectReason {
+        log::info!("Starting listening for messages from bot {}...", self.id);
+        let mut watchdog = interval(Duration::from_secs(1));
+        watchdog.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let message = tokio::select! {
+                message = stream.next() => message,
+                _ = watchdog.tick() => {
+                    if self.check_health() {
+                        continue;
+                    }
+                    if let Err(e) = self.sink.lock().await.close().await {
+                        log::debug!("Error closing connection of bot {}: {}", self.id, e);
                     }
-                })
-                .await;
+                    return DisconnectReason::Timeout;
+                }
+                _ = self.shutdown.notified() => {
+                    return self
+                        .shutdown_reason
+                        .lock()
+                        .unwrap()
+                        .take()
+                        .unwrap_or(DisconnectReason::Shutdown);
+                }
+            };
+            match message {
+                Some(Ok(Message::Text(message))) => self.handle(&message),
+                Some(Ok(Message::Close(frame))) => {
+                    log::info!("Bot {} closed the connection: {:?}", self.id, frame);
+                    return DisconnectReason::Closed;
+                }
+                Some(Ok(message)) => log::warn!("Received non-text message: {:?}", message),
+                Some(Err(e)) => {
+                    log::error!("Error receiving message from bot {}: {}", self.id, e);
+                    return DisconnectReason::Error(e.to_string());
+                }
+                None => return DisconnectReason::Closed,
+            }
+        }
+    }
+
+    fn handle(self: &Arc<Self>, message: &str) {
+        log::debug!("Received event message: {}", message);
+        self.health.lock().unwrap().last_seen = Some(SystemTime::now());
+        match serde_json::from_str::<MinimalEvent>(message) {
+            Ok(data) => {
+                if data.is_meta_event() {
+                    self.handle_meta_event(message);
+                    return;
+                }
+                if !data.is_message() {
+                    log::debug!("Received non-message event: {}, ignored.", message);
+                    return;
+                }
+            }
+            Err(e) => {
+                log::warn!("Error deserializing event minimally: {}", e);
+                return;
+            }
+        };
+        let message_event: MessageEvent = match serde_json::from_str(message) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Error deserializing message: {}", e);
+                return;
+            }
         };
+        let event = OnebotEvent::new(message_event, self.clone());
+        if let Err(e) = self.sender.send(OnebotPayload::Message(Box::new(event))) {
+            log::warn!("Error sending event: {}", e);
+        }
+    }
+
+    /// Close the connection, the listening loop exits immediately.
+    pub async fn close(&self) {
+        self.disconnect(DisconnectReason::Shutdown).await
+    }
+
+    pub(crate) async fn disconnect(&self, reason: DisconnectReason) {
+        self.shutdown_reason.lock().unwrap().get_or_insert(reason);
+        self.shutdown.notify_one();
+        if let Err(e) = self.sink.lock().await.close().await {
+            log::debug!("Error closing connection of bot {}: {}", self.id, e);
+        }
+    }
+
+    async fn call(&self, action: Action) -> Result<()> {
+        let action = serde_json::to_string(&action)?;
+        self.sink.lock().await.send(Message::Text(action)).await?;
+        Ok(())
     }
 
-    pub async fn send(&self, event: &OnebotEvent, message: &str) {
-        if let Some(ws_stream) = &mut unsafe { &mut (*self.inner.get()) }.ws_stream {
-            ws_stream
-                .send(Message::Text(
-                    serde_json::to_string(&Action {
-                        action: if event.is_private() {
-                            "send_private_msg".to_string()
-                        } else {
-                            "send_group_msg".to_string()
-                        },
-                        params: ActionParams {
-                            group_id: if event.is_private() {
-                                None
-                            } else {
-                                event.plain_data.group_id
-                            },
-                            user_id: Some(event.plain_data.user_id),
-                            message: message.to_string(),
-                        },
-                        echo: Some("0".to_string()),
-                    })
-                    .unwrap(),
-                ))
+    /// Reply to the channel where the event comes from.
+    ///
+    /// Fails if a group message event has no group ID.
+    pub async fn send(&self, event: &OnebotEvent, message: AionMessage) -> Result<()> {
+        if event.is_private() {
+            self.send_private_msg(event.plain_data.user_id, message)
                 .await
-                .unwrap();
+        } else {
+            let group_id = event.plain_data.group_id.ok_or_else(|| {
+                anyhow!(
+                    "Group message {} has no group ID",
+                    event.plain_data.message_id
+                )
+            })?;
+            self.send_group_msg(group_id, message).await
         }
     }
+
+    /// Queue the message for the target and wait until it is sent, fails
+    /// immediately if too many messages are queued for the target.
+    pub async fn send_to(&self, target: Target, message: AionMessage) -> Result<(), OutboxError> {
+        self.outbox.send(target, message).await
+    }
+
+    /// Number of messages waiting in the outbox.
+    pub fn queued(&self) -> usize {
+        self.outbox.queued()
+    }
+
+    pub async fn send_private_msg(&self, user_id: i64, message: AionMessage) -> Result<()> {
+        Ok(self.send_to(Target::Private(user_id), message).await?)
+    }
+
+    pub async fn send_group_msg(&self, group_id: i64, message: AionMessage) -> Result<()> {
+        Ok(self.send_to(Target::Group(group_id), message).await?)
+    }
+}
+
+impl Transport for Bot {
+    fn deliver(&self, target: Target, message: AionMessage) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.call(target.action(message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn meta_event(value: serde_json::Value) -> MetaEvent {
+        let mut event = json!({"time": 0, "self_id": 10000, "post_type": "meta_event"});
+        event
+            .as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(event).unwrap()
+    }
+
+    #[test]
+    fn test_heartbeat_thresholds() {
+        let config = HeartbeatConfig::default();
+        let mut health = Health::default();
+        let now = Instant::now();
+        assert!(health.check("10000", &config, now + Duration::from_secs(3600)));
+
+        health.update(
+            "10000",
+            &meta_event(json!({"meta_event_type": "heartbeat", "interval": 1000})),
+        );
+        let start = health.last_heartbeat.unwrap();
+        assert!(health.check("10000", &config, start + Duration::from_millis(1500)));
+        assert!(health.healthy);
+        assert!(health.check("10000", &config, start + Duration::from_millis(2500)));
+        assert!(!health.healthy);
+        assert!(!health.check("10000", &config, start + Duration::from_millis(5500)));
+
+        health.update(
+            "10000",
+            &meta_event(json!({"meta_event_type": "heartbeat", "interval": 1000})),
+        );
+        assert!(health.healthy);
+
+        // Huge intervals and thresholds must not overflow.
+        let config = HeartbeatConfig {
+            unhealthy_after: u32::MAX,
+            close_after: u32::MAX,
+        };
+        health.interval = Some(Duration::MAX);
+        assert!(health.check("10000", &config, start + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_parse_meta_events() {
+        let mut health = Health::default();
+        health.update(
+            "10000",
+            &meta_event(json!({
+                "meta_event_type": "heartbeat",
+                "interval": 0,
+                "status": {"online": false},
+            })),
+        );
+        assert_eq!(health.interval, None);
+        assert!(!health.online);
+        assert!(health.good);
+        assert!(health.check("10000", &HeartbeatConfig::default(), Instant::now()));
+
+        health.update(
+            "10000",
+            &meta_event(json!({
+                "meta_event_type": "heartbeat",
+                "interval": 5000,
+                "status": {"online": true, "good": false},
+            })),
+        );
+        assert_eq!(health.interval, Some(Duration::from_secs(5)));
+        assert!(health.online);
+        assert!(!health.good);
+
+        let lifecycle =
+            |sub_type| meta_event(json!({"meta_event_type": "lifecycle", "sub_type": sub_type}));
+        health.update("10000", &lifecycle("disable"));
+        assert!(!health.online);
+        health.update("10000", &lifecycle("enable"));
+        assert!(health.online);
+    }
 }
@@ -1,14 +1,25 @@
 use std::sync::Arc;
 
-use aionbot_core::event::Event;
-use anyhow::{anyhow, Result};
+use aionbot_core::{
+    connection::ConnectionEvent,
+    event::{Capabilities, Content, Event},
+    identity::{Channel, Role, User},
+    message::Message,
+};
+use anyhow::Result;
 
-use crate::{bot::Bot, models::MessageEvent};
+use crate::{bot::Bot, models::MessageEvent, segment::Segment, ADAPTER_NAME};
 
 #[derive(Clone, Debug)]
 pub struct OnebotEvent {
     pub plain_data: MessageEvent,
     pub bot: Arc<Bot>,
+    text: String,
+    raw_text: String,
+    sender: User,
+    channel: Channel,
+    to_me: bool,
+    mentions: Vec<String>,
 }
 
 impl Event for OnebotEvent {
@@ -16,49 +27,51 @@ impl Event for OnebotEvent {
         &self.plain_data.message_type
     }
 
-    fn content(&self) -> Box<dyn std::any::Any> {
-        let content = self
-            .plain_data
-            .message
-            .iter()
-            .map(|segment| segment.data.text.clone())
-            .collect::<Vec<String>>();
-        let result: &str = content.join("").leak();
-        Box::new(result)
+    fn adapter(&self) -> &str {
+        ADAPTER_NAME
+    }
+
+    fn content(&self) -> Option<Content<'_>> {
+        Some(Content::Text(&self.text))
+    }
+
+    fn plain_data(&self) -> Option<&dyn std::any::Any> {
+        Some(&self.plain_data)
+    }
+
+    fn sender(&self) -> Option<&User> {
+        Some(&self.sender)
     }
 
-    fn plain_data(&self) -> Box<dyn std::any::Any> {
-        Box::new(self.plain_data.clone())
+    fn channel(&self) -> Option<&Channel> {
+        Some(&self.channel)
     }
 
-    fn emitter_id(&self) -> &str {
-        self.plain_data.user_id.to_string().leak()
+    fn is_to_me(&self) -> bool {
+        self.to_me
     }
 
-    fn channel_id(&self) -> Result<&str> {
-        if let Some(group_id) = self.plain_data.group_id {
-            Ok(group_id.to_string().leak())
-        } else {
-            Err(anyhow!(
-                "Group ID not found in this event, \
-            perhaps this is not message from channel?"
-            ))
+    fn mentions(&self) -> &[String] {
+        &self.mentions
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            can_reply: true,
+            has_channel: !self.channel.is_private(),
+            has_sender: true,
         }
     }
 
     fn reply<'s, 'a>(
         &'s self,
-        message: Box<dyn ToString + Send + Sync>,
+        message: Message,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>>
     where
         's: 'a,
     {
         let bot = self.bot.clone();
-        Box::pin(async move {
-            let message = message.to_string();
-            bot.send(self, &message).await;
-            Ok(())
-        })
+        Box::pin(async move { bot.send(self, message).await })
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -67,7 +80,259 @@ impl Event for OnebotEvent {
 }
 
 impl OnebotEvent {
+    pub fn new(plain_data: MessageEvent, bot: Arc<Bot>) -> Self {
+        let fields = Fields::new(&plain_data, bot.nicknames());
+        Self {
+            plain_data,
+            bot,
+            text: fields.text,
+            raw_text: fields.raw_text,
+            sender: fields.sender,
+            channel: fields.channel,
+            to_me: fields.to_me,
+            mentions: fields.mentions,
+        }
+    }
+
+    /// Text of the message before the nickname of the bot is stripped, unlike
+    /// [`Event::content`].
+    pub fn raw_text(&self) -> &str {
+        &self.raw_text
+    }
+
     pub fn is_private(&self) -> bool {
         self.plain_data.message_type == "private"
     }
+
+    /// Look up another bot connected to the same server by its self ID.
+    pub fn bot_by_id(&self, self_id: &str) -> Option<Arc<Bot>> {
+        self.bot.onebot()?.bot(self_id)
+    }
+}
+
+/// Fields of an event derived from the message data.
+struct Fields {
+    text: String,
+    raw_text: String,
+    sender: User,
+    channel: Channel,
+    to_me: bool,
+    mentions: Vec<String>,
+}
+
+impl Fields {
+    fn new(plain_data: &MessageEvent, nicknames: &[String]) -> Self {
+        let self_id = plain_data.self_id.to_string();
+        let mentions: Vec<String> = plain_data
+            .message
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::At { qq, .. } if qq != "all" => Some(qq.to_string()),
+                _ => None,
+            })
+            .collect();
+        let mut to_me = plain_data.group_id.is_none() || mentions.contains(&self_id);
+
+        // Strip the leading replies and mention of the bot itself.
+        let mut text = String::new();
+        let mut leading = true;
+        for segment in &plain_data.message {
+            if leading {
+                match segment {
+                    Segment::Reply { .. } => continue,
+                    Segment::At { qq, .. } if *qq == self_id => continue,
+                    Segment::Text { text, .. } if text.trim().is_empty() => continue,
+                    _ => leading = false,
+                }
+            }
+            if let Some(segment) = segment.as_text() {
+                text.push_str(segment);
+            }
+        }
+        let raw_text = text.trim_start().to_string();
+        let mut text = raw_text.as_str();
+        if let Some(rest) = nicknames
+            .iter()
+            .find_map(|nickname| strip_nickname(text, nickname))
+        {
+            to_me = true;
+            text = rest.trim_start_matches([',', '，', ':', '：']).trim_start();
+        }
+        let text = text.to_string();
+
+        let sender = User {
+            id: plain_data.user_id.to_string(),
+            name: plain_data.sender.nickname.clone(),
+            alias: plain_data.sender.card.clone(),
+            title: plain_data.sender.title.clone(),
+            role: match plain_data.sender.role.as_deref() {
+                Some("owner") => Role::Owner,
+                Some("admin") => Role::Admin,
+                _ => Role::Member,
+            },
+        };
+        let channel = match plain_data.group_id {
+            Some(group_id) => Channel::Group {
+                group_id: group_id.to_string(),
+            },
+            None => Channel::Private {
+                user_id: sender.id.clone(),
+            },
+        };
+        Self {
+            text,
+            raw_text,
+            sender,
+            channel,
+            to_me,
+            mentions,
+        }
+    }
+}
+
+/// Strip the nickname if the text starts with it followed by the end of the
+/// text, whitespace or a separating punctuation mark.
+fn strip_nickname<'a>(text: &'a str, nickname: &str) -> Option<&'a str> {
+    let rest = text.strip_prefix(nickname)?;
+    match rest.chars().next() {
+        None => Some(rest),
+        Some(c) if c.is_whitespace() => Some(rest),
+        Some(',' | ':' | '，' | '：' | '。' | '！' | '？') => Some(rest),
+        Some(_) => None,
+    }
+}
+
+/// Event delivered from the OneBot server to the runtime.
+#[derive(Clone, Debug)]
+pub enum OnebotPayload {
+    Message(Box<OnebotEvent>),
+    Connection(ConnectionEvent),
+}
+
+impl OnebotPayload {
+    pub fn into_event(self) -> Box<dyn Event> {
+        match self {
+            Self::Message(event) => event,
+            Self::Connection(event) => Box::new(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn message_event(group_id: Option<i64>, message: Value) -> MessageEvent {
+        serde_json::from_value(json!({
+            "time": 0,
+            "self_id": 10000,
+            "post_type": "message",
+            "message_type": if group_id.is_some() { "group" } else { "private" },
+            "sub_type": "normal",
+            "message_id": 1,
+            "group_id": group_id,
+            "user_id": 10001,
+            "message": message,
+            "message_format": "array",
+            "raw_message": "",
+            "font": 0,
+            "sender": {"user_id": 10001, "nickname": "alice"},
+        }))
+        .unwrap()
+    }
+
+    fn parse(group_id: Option<i64>, message: Value) -> Fields {
+        Fields::new(&message_event(group_id, message), &["bot".to_string()])
+    }
+
+    #[test]
+    fn test_strip_mention_and_reply() {
+        let fields = parse(
+            Some(20001),
+            json!([
+                {"type": "reply", "data": {"id": "5"}},
+                {"type": "at", "data": {"qq": "10000"}},
+                {"type": "text", "data": {"text": " hello"}},
+            ]),
+        );
+        assert!(fields.to_me);
+        assert_eq!(fields.text, "hello");
+        assert_eq!(fields.mentions, ["10000"]);
+
+        let fields = parse(
+            Some(20001),
+            json!([
+                {"type": "at", "data": {"qq": "10002"}},
+                {"type": "text", "data": {"text": " hello"}},
+            ]),
+        );
+        assert!(!fields.to_me);
+        assert_eq!(fields.text, "hello");
+        assert_eq!(fields.mentions, ["10002"]);
+
+        let fields = parse(None, json!("hello"));
+        assert!(fields.to_me);
+        assert_eq!(fields.text, "hello");
+    }
+
+    #[test]
+    fn test_strip_nickname() {
+        let fields = parse(Some(20001), json!("bot, hello"));
+        assert!(fields.to_me);
+        assert_eq!(fields.text, "hello");
+
+        let fields = parse(Some(20001), json!("bot"));
+        assert!(fields.to_me);
+        assert_eq!(fields.text, "");
+
+        let fields = parse(Some(20001), json!("bother me"));
+        assert!(!fields.to_me);
+        assert_eq!(fields.text, "bother me");
+
+        for text in ["bot's broken", "bot-like", "bot_name", "bot.rs"] {
+            let fields = parse(Some(20001), json!(text));
+            assert!(!fields.to_me, "{text}");
+            assert_eq!(fields.text, text);
+        }
+
+        let fields = parse(Some(20001), json!("bot：hello"));
+        assert!(fields.to_me);
+        assert_eq!(fields.text, "hello");
+        assert_eq!(fields.raw_text, "bot：hello");
+    }
+
+    #[test]
+    fn test_sender_and_channel() {
+        let mut data = message_event(Some(20001), json!("hello"));
+        data.sender.card = Some("Alice".into());
+        data.sender.title = Some("Knight".into());
+        data.sender.role = Some("admin".into());
+        let fields = Fields::new(&data, &[]);
+        assert_eq!(fields.sender.id, "10001");
+        assert_eq!(fields.sender.name.as_deref(), Some("alice"));
+        assert_eq!(fields.sender.display_name(), "Alice");
+        assert_eq!(fields.sender.title.as_deref(), Some("Knight"));
+        assert_eq!(fields.sender.role, Role::Admin);
+        assert_eq!(
+            fields.channel,
+            Channel::Group {
+                group_id: "20001".into()
+            }
+        );
+
+        data.sender.role = Some("owner".into());
+        assert_eq!(Fields::new(&data, &[]).sender.role, Role::Owner);
+
+        let fields = parse(None, json!("hello"));
+        assert_eq!(fields.sender.role, Role::Member);
+        assert_eq!(fields.sender.display_name(), "alice");
+        assert_eq!(
+            fields.channel,
+            Channel::Private {
+                user_id: "10001".into()
+            }
+        );
+    }
 }
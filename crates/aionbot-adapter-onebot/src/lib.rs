@@ -3,37 +3,42 @@ pub extern crate aionbot_core;
 pub mod bot;
 pub mod event;
 pub mod models;
+pub mod outbox;
+pub mod segment;
 pub mod ws;
 
 use std::{any::Any, sync::Arc};
 
 use aionbot_core::{
     event::Event,
+    message::Message,
     runtime::{Runtime, RuntimeStatus, StateManager},
 };
 use anyhow::Result;
-use event::OnebotEvent;
+use event::{OnebotEvent, OnebotPayload};
 use tokio::sync::broadcast::Receiver;
 use ws::Onebot;
 
+pub const ADAPTER_NAME: &str = "onebot";
+
 pub trait Adapter: Any {
-    fn reply(&self, message: &str) -> impl std::future::Future<Output = Result<()>> + Send;
+    fn reply(
+        &self,
+        message: impl Into<Message> + Send,
+    ) -> impl std::future::Future<Output = Result<()>> + Send;
 }
 
 impl Adapter for dyn Event {
-    async fn reply(&self, message: &str) -> Result<()> {
-        let event = unsafe { (self as *const dyn Event as *mut OnebotEvent).as_mut() }.unwrap();
-        let bot = event.bot.clone();
-
-        bot.send(event, message).await;
-        Ok(())
+    async fn reply(&self, message: impl Into<Message> + Send) -> Result<()> {
+        let event = self.try_downcast_ref::<OnebotEvent>()?;
+        event.bot.send(event, message.into()).await
     }
 }
 
 pub struct OnebotRuntime {
     onebot: Option<Arc<Onebot>>,
     state: Arc<StateManager>,
-    receiver: Option<Receiver<Box<OnebotEvent>>>,
+    receiver: Option<Receiver<OnebotPayload>>,
 }
 
 impl Default for OnebotRuntime {
@@ -58,7 +63,12 @@ impl Runtime for OnebotRuntime {
 
     async fn prepare(&mut self) -> Result<()> {
         log::debug!("Preparing for Onebot runtime...");
-        self.onebot = Some(ws::Onebot::new().listen(Default::default()).await?);
+        let config = self
+            .state
+            .try_get::<ws::Config>()
+            .cloned()
+            .unwrap_or_default();
+        self.onebot = Some(ws::Onebot::new().listen(config).await?);
         Ok(())
     }
 
@@ -74,7 +84,7 @@ impl Runtime for OnebotRuntime {
 
     async fn run(&mut self) -> Result<RuntimeStatus> {
         log::debug!("Waiting for Onebot runtime event loop...");
-        let event = self.receiver.as_mut().unwrap().recv().await?;
+        let event = self.receiver.as_mut().unwrap().recv().await?.into_event();
         log::debug!("Received Onebot event of type [{}].", event.event_type());
         Ok(RuntimeStatus::Event(event))
     }
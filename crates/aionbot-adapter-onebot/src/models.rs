@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::segment::{self, Segment};
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MinimalEvent {
     pub time: i64,
@@ -25,6 +27,42 @@ impl MinimalEvent {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Status {
+    pub online: Option<bool>,
+    /// Implementations omitting the field are assumed to be good.
+    #[serde(default = "Status::default_good")]
+    pub good: bool,
+}
+
+impl Status {
+    fn default_good() -> bool {
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MetaEvent {
+    pub time: i64,
+    pub self_id: i64,
+    pub post_type: String,
+    pub meta_event_type: String,
+    pub sub_type: Option<String>,
+    pub status: Option<Status>,
+    /// Heartbeat interval in milliseconds.
+    pub interval: Option<u64>,
+}
+
+impl MetaEvent {
+    pub fn is_heartbeat(&self) -> bool {
+        self.meta_event_type == "heartbeat"
+    }
+
+    pub fn is_lifecycle(&self) -> bool {
+        self.meta_event_type == "lifecycle"
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Sender {
     pub user_id: Option<i64>,
@@ -35,6 +73,7 @@ pub struct Sender {
     pub level: Option<String>,
     pub role: Option<String>,
     pub title: Option<String>,
+    pub card: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -44,20 +83,6 @@ pub struct Anonymous {
     pub flag: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct MessageData {
-    #[serde(default)]
-    pub text: String,
-    #[serde(default)]
-    pub qq: String,
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct MessageSegment {
-    pub r#type: String,
-    pub data: MessageData,
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MessageEvent {
     pub time: i64,
@@ -69,7 +94,8 @@ pub struct MessageEvent {
     pub group_id: Option<i64>,
     pub user_id: i64,
     pub anonymous: Option<String>,
-    pub message: Vec<MessageSegment>,
+    #[serde(deserialize_with = "segment::deserialize_message")]
+    pub message: Vec<Segment>,
     pub message_format: String,
     pub raw_message: String,
     pub font: i32,
@@ -80,7 +106,7 @@ pub struct MessageEvent {
 pub struct ActionParams {
     pub group_id: Option<i64>,
     pub user_id: Option<i64>,
-    pub message: String,
+    pub message: Vec<Segment>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
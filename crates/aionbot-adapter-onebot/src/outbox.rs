@@ -0,0 +1,442 @@
+use std::{
+    collections::HashMap,
+    fmt, mem,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use aionbot_core::{
+    limiter::{RateLimit, RateLimiter},
+    message::{Message, Segment},
+};
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot,
+};
+
+use crate::{
+    models::{Action, ActionParams},
+    segment,
+};
+
+/// Receiver of outgoing messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    Private(i64),
+    Group(i64),
+}
+
+impl Target {
+    pub(crate) fn action(self, message: Message) -> Action {
+        let (action, group_id, user_id) = match self {
+            Self::Private(user_id) => ("send_private_msg", None, Some(user_id)),
+            Self::Group(group_id) => ("send_group_msg", Some(group_id), None),
+        };
+        Action {
+            action: action.to_string(),
+            params: ActionParams {
+                group_id,
+                user_id,
+                message: segment::from_message(message),
+            },
+            echo: Some("0".to_string()),
+        }
+    }
+
+    fn key(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutboxConfig {
+    /// Rate of messages sent by the bot to all targets.
+    pub bot_rate: RateLimit,
+    /// Rate of messages sent to each target.
+    pub target_rate: RateLimit,
+    /// Messages queued per target before sending fails with
+    /// [`OutboxError::Full`].
+    pub capacity: usize,
+    /// Characters of text per message, longer messages are split. `0` disables
+    /// splitting.
+    pub max_length: usize,
+    /// Merge text messages queued for the same target into one message.
+    pub merge: bool,
+    /// Time after which the worker of an idle target stops.
+    pub idle: Duration,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            bot_rate: RateLimit::new(20, Duration::from_secs(10)),
+            target_rate: RateLimit::new(5, Duration::from_secs(5)),
+            capacity: 64,
+            max_length: 3000,
+            merge: false,
+            idle: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutboxError {
+    /// Too many messages are queued for the target.
+    Full,
+    /// The bot is disconnected.
+    Closed,
+    /// The message could not be written to the connection.
+    Failed(String),
+}
+
+impl fmt::Display for OutboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => f.write_str("outbox is full"),
+            Self::Closed => f.write_str("bot is disconnected"),
+            Self::Failed(e) => write!(f, "failed to send message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OutboxError {}
+
+/// Connection the outbox delivers messages through.
+pub(crate) trait Transport: Send + Sync {
+    fn deliver(&self, target: Target, message: Message) -> BoxFuture<'_, Result<()>>;
+}
+
+struct Outgoing {
+    message: Message,
+    responders: Vec<oneshot::Sender<Result<(), OutboxError>>>,
+}
+
+/// Queues of outgoing messages of a bot, each target is served in order by
+/// its own worker.
+pub(crate) struct Outbox {
+    config: OutboxConfig,
+    limiter: RateLimiter,
+    queues: Mutex<HashMap<Target, mpsc::Sender<Outgoing>>>,
+    transport: Weak<dyn Transport>,
+}
+
+impl fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outbox")
+            .field("config", &self.config)
+            .field("queued", &self.queued())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Outbox {
+    pub fn new(transport: Weak<dyn Transport>, config: OutboxConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            limiter: RateLimiter::new(),
+            queues: Default::default(),
+            transport,
+        })
+    }
+
+    /// Queue the message and wait until it is sent.
+    pub async fn send(
+        self: &Arc<Self>,
+        target: Target,
+        message: Message,
+    ) -> Result<(), OutboxError> {
+        let (sender, receiver) = oneshot::channel();
+        self.enqueue(
+            target,
+            Outgoing {
+                message,
+                responders: vec![sender],
+            },
+        )?;
+        receiver.await.unwrap_or(Err(OutboxError::Closed))
+    }
+
+    /// Number of messages waiting to be sent.
+    pub fn queued(&self) -> usize {
+        self.queues
+            .lock()
+            .unwrap()
+            .values()
+            .map(|queue| queue.max_capacity() - queue.capacity())
+            .sum()
+    }
+
+    fn enqueue(
+        self: &Arc<Self>,
+        target: Target,
+        mut outgoing: Outgoing,
+    ) -> Result<(), OutboxError> {
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(queue) = queues.get(&target) {
+            match queue.try_send(outgoing) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(_)) => return Err(OutboxError::Full),
+                Err(TrySendError::Closed(closed)) => outgoing = closed,
+            }
+        }
+        let (sender, receiver) = mpsc::channel(self.config.capacity.max(1));
+        sender.try_send(outgoing).map_err(|_| OutboxError::Closed)?;
+        queues.insert(target, sender);
+        tokio::spawn(self.clone().serve(target, receiver));
+        Ok(())
+    }
+
+    async fn serve(self: Arc<Self>, target: Target, mut receiver: mpsc::Receiver<Outgoing>) {
+        let mut next = None;
+        loop {
+            let mut outgoing = match next.take() {
+                Some(outgoing) => outgoing,
+                None => match tokio::time::timeout(self.config.idle, receiver.recv()).await {
+                    Ok(Some(outgoing)) => outgoing,
+                    Ok(None) => return,
+                    Err(_) => {
+                        // Messages are queued with the lock held, so none can
+                        // be lost between the check and the removal.
+                        let mut queues = self.queues.lock().unwrap();
+                        if receiver.is_empty() {
+                            queues.remove(&target);
+                            return;
+                        }
+                        continue;
+                    }
+                },
+            };
+            self.acquire(target).await;
+            if self.config.merge {
+                while let Ok(queued) = receiver.try_recv() {
+                    match merge(outgoing, queued, self.config.max_length) {
+                        Ok(merged) => outgoing = merged,
+                        Err((first, second)) => {
+                            outgoing = first;
+                            next = Some(second);
+                            break;
+                        }
+                    }
+                }
+            }
+            let result = self.deliver(target, outgoing.message).await;
+            for responder in outgoing.responders {
+                let _ = responder.send(result.clone());
+            }
+        }
+    }
+
+    async fn deliver(&self, target: Target, message: Message) -> Result<(), OutboxError> {
+        for (i, chunk) in split(message, self.config.max_length)
+            .into_iter()
+            .enumerate()
+        {
+            if i > 0 {
+                self.acquire(target).await;
+            }
+            let transport = self.transport.upgrade().ok_or(OutboxError::Closed)?;
+            transport
+                .deliver(target, chunk)
+                .await
+                .map_err(|e| OutboxError::Failed(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Wait until both the bot and the target may send a message.
+    async fn acquire(&self, target: Target) {
+        let key = target.key();
+        let limits = [
+            ("", &self.config.bot_rate),
+            (key.as_str(), &self.config.target_rate),
+        ];
+        while let Err(retry_after) = self.limiter.acquire(limits) {
+            tokio::time::sleep(retry_after).await;
+        }
+    }
+}
+
+/// Merge two text messages into one if it is not too long.
+fn merge(
+    first: Outgoing,
+    second: Outgoing,
+    max_length: usize,
+) -> Result<Outgoing, (Outgoing, Outgoing)> {
+    let is_text = |message: &Message| {
+        message
+            .segments()
+            .iter()
+            .all(|segment| matches!(segment, Segment::Text(_)))
+    };
+    if !is_text(&first.message) || !is_text(&second.message) {
+        return Err((first, second));
+    }
+    let text = format!(
+        "{}\n{}",
+        first.message.plain_text(),
+        second.message.plain_text()
+    );
+    if max_length > 0 && text.chars().count() > max_length {
+        return Err((first, second));
+    }
+    let mut responders = first.responders;
+    responders.extend(second.responders);
+    Ok(Outgoing {
+        message: Message::from(text),
+        responders,
+    })
+}
+
+/// Split the text of the message into messages of at most `max_length`
+/// characters, preferring to split at line breaks.
+pub fn split(message: Message, max_length: usize) -> Vec<Message> {
+    if max_length == 0 {
+        return vec![message];
+    }
+    let mut messages = vec![];
+    let mut current = Message::new();
+    let mut length = 0;
+    for segment in message.segments() {
+        let Segment::Text(text) = segment else {
+            current.push(segment.clone());
+            continue;
+        };
+        let mut rest = text.as_str();
+        while !rest.is_empty() {
+            if length == max_length {
+                messages.push(mem::take(&mut current));
+                length = 0;
+            }
+            let (head, tail) = split_text(rest, max_length - length);
+            if !head.is_empty() {
+                current.push(Segment::Text(head.to_string()));
+                length += head.chars().count();
+            }
+            rest = tail;
+            if !rest.is_empty() {
+                messages.push(mem::take(&mut current));
+                length = 0;
+            }
+        }
+    }
+    if !current.is_empty() || messages.is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+fn split_text(text: &str, max_length: usize) -> (&str, &str) {
+    let Some((end, c)) = text.char_indices().nth(max_length) else {
+        return (text, "");
+    };
+    // The first character beyond the limit may be the line break to drop.
+    match text[..end + c.len_utf8()].rfind('\n') {
+        Some(newline) if newline > 0 => (&text[..newline], &text[newline + 1..]),
+        _ => text.split_at(end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::Semaphore;
+
+    use super::*;
+
+    struct Recorder {
+        sent: Mutex<Vec<(Target, String)>>,
+        permits: Semaphore,
+    }
+
+    impl Transport for Recorder {
+        fn deliver(&self, target: Target, message: Message) -> BoxFuture<'_, Result<()>> {
+            Box::pin(async move {
+                self.permits.acquire().await?.forget();
+                self.sent
+                    .lock()
+                    .unwrap()
+                    .push((target, message.plain_text()));
+                Ok(())
+            })
+        }
+    }
+
+    fn config() -> OutboxConfig {
+        OutboxConfig {
+            bot_rate: RateLimit::new(100, Duration::from_millis(10)),
+            target_rate: RateLimit::new(100, Duration::from_millis(10)),
+            capacity: 2,
+            max_length: 10,
+            merge: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let texts =
+            |messages: Vec<Message>| messages.iter().map(Message::plain_text).collect::<Vec<_>>();
+        assert_eq!(texts(split(Message::from("hello"), 10)), ["hello"]);
+        assert_eq!(
+            texts(split(Message::from("hello world, bye"), 10)),
+            ["hello worl", "d, bye"]
+        );
+        assert_eq!(
+            texts(split(Message::from("hello\nworld, bye"), 10)),
+            ["hello", "world, bye"]
+        );
+        let message = Message::new().text("héllo").mention("1").text(" wörld!");
+        let messages = split(message, 8);
+        assert_eq!(texts(messages.clone()), ["héllo wö", "rld!"]);
+        assert_eq!(messages[0].segments().len(), 3);
+        assert_eq!(texts(split(Message::from("你好世界"), 2)), ["你好", "世界"]);
+        assert_eq!(
+            texts(split(Message::from("你好\n世界和平"), 3)),
+            ["你好", "世界和", "平"]
+        );
+        assert_eq!(split(Message::new(), 10), [Message::new()]);
+    }
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let recorder = Arc::new(Recorder {
+            sent: Mutex::new(vec![]),
+            permits: Semaphore::new(0),
+        });
+        let transport: Arc<dyn Transport> = recorder.clone();
+        let outbox = Outbox::new(Arc::downgrade(&transport), config());
+        let target = Target::Group(10001);
+
+        let send = |text: &'static str| {
+            let outbox = outbox.clone();
+            tokio::spawn(async move { outbox.send(target, Message::from(text)).await })
+        };
+        // The first message blocks the worker, the next two are queued.
+        let mut sends = vec![send("a")];
+        while !outbox.queues.lock().unwrap().contains_key(&target) || outbox.queued() > 0 {
+            tokio::task::yield_now().await;
+        }
+        sends.extend([send("b"), send("c")]);
+        while outbox.queued() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            outbox.send(target, Message::from("d")).await,
+            Err(OutboxError::Full)
+        );
+
+        recorder.permits.add_permits(2);
+        for send in sends {
+            assert_eq!(send.await.unwrap(), Ok(()));
+        }
+        let sent = recorder.sent.lock().unwrap().clone();
+        assert_eq!(sent, [(target, "a".into()), (target, "b\nc".into())]);
+
+        drop((transport, recorder));
+        assert_eq!(
+            outbox.send(target, Message::from("e")).await,
+            Err(OutboxError::Closed)
+        );
+    }
+}
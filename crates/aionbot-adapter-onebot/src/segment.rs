@@ -0,0 +1,697 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use aionbot_core::message::{self, Image, Message};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+/// Message segment defined by OneBot v11.
+///
+/// Segments with a type unknown to this crate, or whose data does not
+/// fit the typed variant, are kept as [`Segment::Unknown`], and fields
+/// unknown to a typed variant are kept in its `extra` map, so that they
+/// survive a deserialize-serialize round trip untouched.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Segment {
+    Text {
+        text: String,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Face {
+        id: Scalar,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Image {
+        file: String,
+        #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+        kind: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        proxy: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout: Option<Scalar>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Record {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        magic: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        proxy: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout: Option<Scalar>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Video {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        proxy: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout: Option<Scalar>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    At {
+        /// The mentioned user ID, or `all` for mentioning everyone.
+        qq: Scalar,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Rps {
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Dice {
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Shake {
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Poke {
+        #[serde(rename = "type")]
+        kind: Scalar,
+        id: Scalar,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Anonymous {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ignore: Option<Scalar>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Share {
+        url: String,
+        title: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        image: Option<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Contact {
+        #[serde(rename = "type")]
+        kind: String,
+        id: Scalar,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Location {
+        lat: Scalar,
+        lon: Scalar,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Music {
+        /// Either a platform name (`qq`, `163`, `xm`) or `custom`.
+        #[serde(rename = "type")]
+        kind: Scalar,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        audio: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        image: Option<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Reply {
+        id: Scalar,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Forward {
+        id: Scalar,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Node {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user_id: Option<Scalar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nickname: Option<String>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            deserialize_with = "optional_message"
+        )]
+        content: Option<Vec<Segment>>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Xml {
+        data: String,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Json {
+        data: String,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    #[serde(untagged)]
+    Unknown {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default)]
+        data: Value,
+    },
+}
+
+impl Segment {
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        Self::Text {
+            text: text.into(),
+            extra: Map::new(),
+        }
+    }
+
+    pub fn at<S: Into<String>>(qq: S) -> Self {
+        Self::At {
+            qq: qq.into().into(),
+            name: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn at_all() -> Self {
+        Self::at("all")
+    }
+
+    pub fn face<S: Into<String>>(id: S) -> Self {
+        Self::Face {
+            id: id.into().into(),
+            extra: Map::new(),
+        }
+    }
+
+    pub fn image<S: Into<String>>(file: S) -> Self {
+        Self::Image {
+            file: file.into(),
+            kind: None,
+            url: None,
+            cache: None,
+            proxy: None,
+            timeout: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn reply<S: Into<String>>(id: S) -> Self {
+        Self::Reply {
+            id: id.into().into(),
+            extra: Map::new(),
+        }
+    }
+
+    /// Get the segment type as it appears on the wire.
+    pub fn kind(&self) -> &str {
+        match self {
+            Self::Text { .. } => "text",
+            Self::Face { .. } => "face",
+            Self::Image { .. } => "image",
+            Self::Record { .. } => "record",
+            Self::Video { .. } => "video",
+            Self::At { .. } => "at",
+            Self::Rps { .. } => "rps",
+            Self::Dice { .. } => "dice",
+            Self::Shake { .. } => "shake",
+            Self::Poke { .. } => "poke",
+            Self::Anonymous { .. } => "anonymous",
+            Self::Share { .. } => "share",
+            Self::Contact { .. } => "contact",
+            Self::Location { .. } => "location",
+            Self::Music { .. } => "music",
+            Self::Reply { .. } => "reply",
+            Self::Forward { .. } => "forward",
+            Self::Node { .. } => "node",
+            Self::Xml { .. } => "xml",
+            Self::Json { .. } => "json",
+            Self::Unknown { kind, .. } => kind,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        if let Self::Text { text, .. } = self {
+            Some(text)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_at_all(&self) -> bool {
+        matches!(self, Self::At { qq, .. } if qq == "all")
+    }
+
+    /// Serialize the segment into CQ code, plain text is escaped as is.
+    pub fn to_cq_code(&self) -> String {
+        if let Self::Text { text, .. } = self {
+            return escape(text, false);
+        }
+        let mut code = format!("[CQ:{}", self.kind());
+        let value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(Value::Object(data)) = value.get("data") {
+            for (key, value) in data {
+                let value = match value {
+                    Value::Null => continue,
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                code.push(',');
+                code.push_str(key);
+                code.push('=');
+                code.push_str(&escape(&value, true));
+            }
+        }
+        code.push(']');
+        code
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_cq_code())
+    }
+}
+
+impl From<message::Segment> for Segment {
+    fn from(segment: message::Segment) -> Self {
+        match segment {
+            message::Segment::Text(text) => Self::text(text),
+            message::Segment::Mention(user_id) => Self::at(user_id),
+            message::Segment::Image(image) => Self::image(match image {
+                Image::Url(url) => url,
+                Image::Path(path) => {
+                    let path = std::path::absolute(&path).unwrap_or(path);
+                    format!(
+                        "file:///{}",
+                        path.to_string_lossy()
+                            .replace('\\', "/")
+                            .trim_start_matches('/')
+                    )
+                }
+                Image::Bytes(bytes) => format!("base64://{}", BASE64_STANDARD.encode(bytes)),
+            }),
+            message::Segment::Reply(message_id) => Self::reply(message_id),
+            message::Segment::Emoji(id) => Self::face(id),
+        }
+    }
+}
+
+/// Convert a platform-neutral message into OneBot segments.
+pub fn from_message(message: Message) -> Vec<Segment> {
+    message.into_iter().map(Into::into).collect()
+}
+
+impl From<&str> for Segment {
+    fn from(text: &str) -> Self {
+        Self::text(text)
+    }
+}
+
+impl From<String> for Segment {
+    fn from(text: String) -> Self {
+        Self::text(text)
+    }
+}
+
+fn escape(text: &str, param: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '[' => escaped.push_str("&#91;"),
+            ']' => escaped.push_str("&#93;"),
+            ',' if param => escaped.push_str("&#44;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&#44;", ",")
+        .replace("&#91;", "[")
+        .replace("&#93;", "]")
+        .replace("&amp;", "&")
+}
+
+fn parse_cq_code(code: &str) -> Option<Segment> {
+    let mut parts = code.split(',');
+    let kind = parts.next()?.strip_prefix("CQ:")?;
+    if kind.is_empty() {
+        return None;
+    }
+    let mut data = Map::new();
+    for part in parts {
+        let (key, value) = part.split_once('=').unwrap_or((part, ""));
+        data.insert(key.to_string(), Value::String(unescape(value)));
+    }
+    let mut segment = Map::new();
+    segment.insert("type".into(), Value::String(kind.to_string()));
+    segment.insert("data".into(), Value::Object(data));
+    serde_json::from_value(Value::Object(segment)).ok()
+}
+
+/// Parse a message in CQ code string format into segments.
+///
+/// Malformed CQ codes are kept as plain text.
+pub fn from_cq_string(message: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find("[CQ:") {
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        match parse_cq_code(&rest[start + 1..end]) {
+            Some(segment) => {
+                if !text.is_empty() {
+                    segments.push(Segment::text(unescape(&text)));
+                    text.clear();
+                }
+                segments.push(segment);
+            }
+            None => text.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        segments.push(Segment::text(unescape(&text)));
+    }
+    segments
+}
+
+/// Serialize segments into a CQ code string.
+pub fn to_cq_string(segments: &[Segment]) -> String {
+    segments.iter().map(Segment::to_cq_code).collect()
+}
+
+/// Deserialize a message given either as a segment array, a single
+/// segment or a CQ code string.
+pub fn deserialize_message<'de, D>(deserializer: D) -> Result<Vec<Segment>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        CqString(String),
+        Segments(Vec<Segment>),
+        Segment(Segment),
+    }
+
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::CqString(message) => from_cq_string(&message),
+        Repr::Segments(segments) => segments,
+        Repr::Segment(segment) => vec![segment],
+    })
+}
+
+fn optional_message<'de, D>(deserializer: D) -> Result<Option<Vec<Segment>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_message(deserializer).map(Some)
+}
+
+/// Field which implementations send either as a string or a number.
+///
+/// The value is accessed as a string and serialized back in the form it
+/// was received, values created from strings are serialized as strings.
+#[derive(Clone, Debug, Default, Eq)]
+pub struct Scalar {
+    value: String,
+    number: bool,
+}
+
+impl Scalar {
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// Check whether the value was received as a number or boolean.
+    pub fn is_number(&self) -> bool {
+        self.number
+    }
+}
+
+impl Deref for Scalar {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.value)
+    }
+}
+
+impl From<String> for Scalar {
+    fn from(value: String) -> Self {
+        Self {
+            value,
+            number: false,
+        }
+    }
+}
+
+impl From<&str> for Scalar {
+    fn from(value: &str) -> Self {
+        Self::from(value.to_string())
+    }
+}
+
+impl From<Scalar> for String {
+    fn from(scalar: Scalar) -> Self {
+        scalar.value
+    }
+}
+
+/// Scalars compare by value regardless of their wire form.
+impl PartialEq for Scalar {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Hash for Scalar {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl PartialEq<str> for Scalar {
+    fn eq(&self, other: &str) -> bool {
+        self.value == other
+    }
+}
+
+impl PartialEq<&str> for Scalar {
+    fn eq(&self, other: &&str) -> bool {
+        self.value == *other
+    }
+}
+
+impl PartialEq<String> for Scalar {
+    fn eq(&self, other: &String) -> bool {
+        self.value == *other
+    }
+}
+
+impl Serialize for Scalar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.number {
+            if let Ok(value) = self.value.parse::<serde_json::Number>() {
+                return value.serialize(serializer);
+            }
+            if let Ok(value) = self.value.parse::<bool>() {
+                return serializer.serialize_bool(value);
+            }
+        }
+        serializer.serialize_str(&self.value)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scalar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (value, number) = match Value::deserialize(deserializer)? {
+            Value::String(value) => (value, false),
+            Value::Number(value) => (value.to_string(), true),
+            Value::Bool(value) => (value.to_string(), true),
+            value => {
+                return Err(de::Error::custom(format!(
+                    "expected string or number, found {}",
+                    value
+                )))
+            }
+        };
+        Ok(Self { value, number })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_segment_serde() {
+        let segments: Vec<Segment> = serde_json::from_value(json!([
+            {"type": "text", "data": {"text": "hello"}},
+            {"type": "at", "data": {"qq": 10001}},
+            {"type": "face", "data": {"id": "14"}},
+            {"type": "image", "data": {"file": "a.jpg", "url": "http://a/a.jpg"}},
+            {"type": "rps", "data": {}},
+            {"type": "music", "data": {"type": "custom", "url": "u", "audio": "a", "title": "t"}},
+        ]))
+        .unwrap();
+        assert_eq!(segments[0], Segment::text("hello"));
+        assert_eq!(segments[1], Segment::at("10001"));
+        assert_eq!(segments[2], Segment::face("14"));
+        assert_eq!(segments[3].kind(), "image");
+        assert_eq!(segments[4], Segment::Rps { extra: Map::new() });
+        assert_eq!(segments[5].kind(), "music");
+        assert_eq!(
+            serde_json::to_value(&segments[1]).unwrap(),
+            json!({"type": "at", "data": {"qq": 10001}})
+        );
+        assert_eq!(
+            serde_json::to_value(Segment::at("10001")).unwrap(),
+            json!({"type": "at", "data": {"qq": "10001"}})
+        );
+    }
+
+    #[test]
+    fn test_typed_segment_round_trip() {
+        let raw = json!([
+            {"type": "at", "data": {"qq": 10001, "name": "alice", "color": 3}},
+            {"type": "image", "data": {"file": "a.jpg", "sub_type": 0, "summary": "[image]"}},
+            {"type": "location", "data": {"lat": 39.9, "lon": "116.4"}},
+            {"type": "poke", "data": {"type": 1, "id": "-1", "strength": 5}},
+        ]);
+        let segments: Vec<Segment> = serde_json::from_value(raw.clone()).unwrap();
+        assert!(matches!(&segments[0], Segment::At { qq, .. } if qq == "10001"));
+        assert!(matches!(&segments[1], Segment::Image { extra, .. } if extra.len() == 2));
+        assert_eq!(serde_json::to_value(&segments).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_unknown_segment_round_trip() {
+        let raw = json!({"type": "markdown", "data": {"content": "# title", "extra": [1, 2]}});
+        let segment: Segment = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(segment.kind(), "markdown");
+        assert_eq!(serde_json::to_value(&segment).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_cq_string() {
+        let segments = from_cq_string("[CQ:at,qq=all] hi &#91;1&#93;[CQ:face,id=1][CQ:bad");
+        assert_eq!(
+            segments,
+            vec![
+                Segment::at_all(),
+                Segment::text(" hi [1]"),
+                Segment::face("1"),
+                Segment::text("[CQ:bad"),
+            ]
+        );
+        assert_eq!(
+            to_cq_string(&[
+                Segment::text("a&[b]"),
+                Segment::Share {
+                    url: "http://x".into(),
+                    title: "a,b".into(),
+                    content: None,
+                    image: None,
+                    extra: Map::new(),
+                },
+            ]),
+            "a&amp;&#91;b&#93;[CQ:share,title=a&#44;b,url=http://x]"
+        );
+    }
+
+    #[test]
+    fn test_from_message() {
+        let message = Message::new()
+            .reply_to("7")
+            .mention("10001")
+            .text(" hi")
+            .image_bytes(b"abc".to_vec())
+            .emoji("14");
+        assert_eq!(
+            from_message(message),
+            vec![
+                Segment::reply("7"),
+                Segment::at("10001"),
+                Segment::text(" hi"),
+                Segment::image("base64://YWJj"),
+                Segment::face("14"),
+            ]
+        );
+        assert_eq!(
+            Segment::from(message::Segment::Image(Image::Path("/tmp/a.png".into()))),
+            Segment::image("file:///tmp/a.png")
+        );
+    }
+
+    #[test]
+    fn test_deserialize_message() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            #[serde(deserialize_with = "deserialize_message")]
+            message: Vec<Segment>,
+        }
+        let wrapper: Wrapper =
+            serde_json::from_value(json!({"message": "[CQ:reply,id=12]ok"})).unwrap();
+        assert_eq!(
+            wrapper.message,
+            vec![Segment::reply("12"), Segment::text("ok")]
+        );
+    }
+}
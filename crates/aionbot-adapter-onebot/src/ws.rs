@@ -1,26 +1,59 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
+use aionbot_core::connection::{ConnectionEvent, DisconnectReason};
 use anyhow::Result;
+use futures_util::StreamExt;
 use tokio::{
-    net::TcpListener,
+    net::{TcpListener, TcpStream},
     sync::{broadcast, Mutex},
     task::JoinHandle,
 };
 use tokio_tungstenite::{
     accept_hdr_async,
-    tungstenite::handshake::server::{Request, Response},
+    tungstenite::{
+        handshake::server::{Callback, ErrorResponse, Request, Response},
+        http::StatusCode,
+    },
 };
 
-use crate::{bot::Bot, event::OnebotEvent};
+use crate::{
+    bot::{Bot, BotStatus},
+    event::OnebotPayload,
+    outbox::OutboxConfig,
+    ADAPTER_NAME,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeartbeatConfig {
+    /// Missed heartbeat intervals before a bot is marked unhealthy.
+    pub unhealthy_after: u32,
+    /// Missed heartbeat intervals before the connection is closed.
+    pub close_after: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            unhealthy_after: 2,
+            close_after: 5,
+        }
+    }
+}
 
+#[derive(Clone, Debug)]
 pub struct Config {
-    host: String,
-    port: u16,
+    pub host: String,
+    pub port: u16,
     pub path: String,
     pub access_token: Option<String>,
+    pub heartbeat: HeartbeatConfig,
+    /// Names of the bot, messages starting with one of them are addressed to the bot.
+    pub nicknames: Vec<String>,
+    pub outbox: OutboxConfig,
 }
 
 impl Default for Config {
@@ -30,19 +63,22 @@ impl Default for Config {
             port: 8080,
             path: "/onebot/v11".to_string(),
             access_token: None,
+            heartbeat: HeartbeatConfig::default(),
+            nicknames: Vec::new(),
+            outbox: OutboxConfig::default(),
         }
     }
 }
 
 pub struct Onebot {
-    sender: broadcast::Sender<Box<OnebotEvent>>,
-    listen_handle: Mutex<Option<JoinHandle<Result<()>>>>,
+    sender: broadcast::Sender<OnebotPayload>,
+    listen_handle: Mutex<Option<JoinHandle<()>>>,
     bots: RwLock<HashMap<String, Arc<Bot>>>,
 }
 
 impl Default for Onebot {
     fn default() -> Self {
-        let (tx, _) = broadcast::channel::<Box<OnebotEvent>>(1024);
+        let (tx, _) = broadcast::channel::<OnebotPayload>(1024);
         Self {
             sender: tx,
             listen_handle: Mutex::new(None),
@@ -58,6 +94,7 @@ impl Onebot {
 
     pub async fn listen(self: Arc<Self>, config: Config) -> Result<Arc<Self>> {
         let onebot = self.clone();
+        let config = Arc::new(config);
 
         let bind_addr = format!("{}:{}", config.host, config.port);
         log::debug!("Trying to bind on {}.", bind_addr);
@@ -74,36 +111,138 @@ impl Onebot {
             .lock()
             .await
             .replace(tokio::spawn(async move {
-                while let Ok((stream, _)) = tcp_listener.accept().await {
-                    let bot = Bot::new(onebot.sender.clone());
-                    let ws_stream =
-                        accept_hdr_async(stream, |req: &Request, response: Response| {
-                            let headers = req.headers();
-                            let bot_id = headers
-                                .get("X-Self-ID")
-                                .map(|id| id.to_str().unwrap().to_string())
-                                .unwrap_or_default();
-                            log::info!("New bot connected with ID: {}.", bot_id);
-                            bot.set_id(bot_id.to_string());
-                            onebot.bots.write().unwrap().insert(bot_id, bot.clone());
-                            Ok(response)
-                        })
-                        .await?;
-                    bot.set_ws_stream(ws_stream);
-                    bot.listen().await;
+                loop {
+                    let (stream, addr) = match tcp_listener.accept().await {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            // Errors such as running out of file descriptors
+                            // persist for a while, back off before retrying.
+                            log::error!("Error accepting connection: {}", e);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                    };
+                    log::debug!("Accepted connection from {}.", addr);
+                    tokio::spawn(onebot.clone().serve(stream, config.clone()));
                 }
-                Ok(())
             }));
         Ok(self)
     }
 
-    pub async fn subscribe(self: Arc<Self>) -> broadcast::Receiver<Box<OnebotEvent>> {
+    async fn serve(self: Arc<Self>, stream: TcpStream, config: Arc<Config>) {
+        let mut self_id = None;
+        let ws_stream = match accept_hdr_async(stream, SelfIdCallback(&mut self_id)).await {
+            Ok(ws_stream) => ws_stream,
+            Err(e) => {
+                log::error!("Error accepting websocket connection: {}", e);
+                return;
+            }
+        };
+        let Some(self_id) = self_id else {
+            return;
+        };
+
+        let (sink, stream) = ws_stream.split();
+        let bot = Bot::new(
+            self_id.clone(),
+            sink,
+            self.sender.clone(),
+            Arc::downgrade(&self),
+            config,
+        );
+        let replaced = self
+            .bots
+            .write()
+            .unwrap()
+            .insert(self_id.clone(), bot.clone());
+        // The replaced connection is reported here, before the new one, as
+        // its own task only reports connections still registered.
+        if let Some(replaced) = replaced {
+            log::warn!(
+                "Bot {} connected again, closing the previous connection.",
+                self_id
+            );
+            replaced.disconnect(DisconnectReason::Replaced).await;
+            self.emit(ConnectionEvent::disconnected(
+                ADAPTER_NAME,
+                &self_id,
+                DisconnectReason::Replaced,
+            ));
+        }
+        log::info!("New bot connected with ID: {}.", self_id);
+        self.emit(ConnectionEvent::connected(ADAPTER_NAME, &self_id));
+
+        let reason = bot.clone().listen(stream).await;
+
+        let mut bots = self.bots.write().unwrap();
+        let current = bots
+            .get(&self_id)
+            .is_some_and(|current| Arc::ptr_eq(current, &bot));
+        if current {
+            bots.remove(&self_id);
+        }
+        drop(bots);
+        log::info!("Bot {} disconnected: {}.", self_id, reason);
+        if current {
+            self.emit(ConnectionEvent::disconnected(ADAPTER_NAME, self_id, reason));
+        }
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        if let Err(e) = self.sender.send(OnebotPayload::Connection(event)) {
+            log::debug!("No subscriber for connection event: {}", e);
+        }
+    }
+
+    /// Get a connected bot by its self ID.
+    pub fn bot(&self, self_id: &str) -> Option<Arc<Bot>> {
+        self.bots.read().unwrap().get(self_id).cloned()
+    }
+
+    /// Get all connected bots.
+    pub fn bots(&self) -> Vec<Arc<Bot>> {
+        self.bots.read().unwrap().values().cloned().collect()
+    }
+
+    /// Get the status of all connected bots.
+    pub fn statuses(&self) -> Vec<BotStatus> {
+        self.bots
+            .read()
+            .unwrap()
+            .values()
+            .map(|bot| bot.status())
+            .collect()
+    }
+
+    pub async fn subscribe(self: Arc<Self>) -> broadcast::Receiver<OnebotPayload> {
         self.sender.subscribe()
     }
 
-    pub async fn close(&mut self) {
+    pub async fn close(&self) {
         if let Some(handle) = self.listen_handle.lock().await.take() {
             handle.abort();
         }
+        for bot in self.bots() {
+            bot.close().await;
+        }
+    }
+}
+
+/// Handshake callback reading the self ID of the connecting bot.
+struct SelfIdCallback<'a>(&'a mut Option<String>);
+
+impl Callback for SelfIdCallback<'_> {
+    fn on_request(self, req: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        match req.headers().get("X-Self-ID").map(|id| id.to_str()) {
+            Some(Ok(id)) if !id.is_empty() => {
+                *self.0 = Some(id.to_string());
+                Ok(response)
+            }
+            _ => {
+                let mut response = ErrorResponse::new(Some("Missing X-Self-ID header".into()));
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                Err(response)
+            }
+        }
     }
 }
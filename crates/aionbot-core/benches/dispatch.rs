@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use aionbot_core::{
+    entry::Entry,
+    event::Event,
+    handler::Handler,
+    router::{CommandRouter, Router},
+    types::HandlerCallback,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+
+const COMMANDS: usize = 500;
+
+fn callback(_event: Arc<Box<dyn Event>>) -> HandlerCallback {
+    Box::pin(async { Ok(()) })
+}
+
+/// Router without a descriptor, forcing the handler to evaluate it.
+struct OpaqueRouter(CommandRouter);
+
+impl Router for OpaqueRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.0.matches(event)
+    }
+}
+
+fn handler<R: Router + 'static>(router: impl Fn(String) -> R) -> Handler {
+    Handler::new(
+        (0..COMMANDS)
+            .map(|i| Entry {
+                id: Box::leak(format!("command{}", i).into_boxed_str()),
+                priority: 0,
+                router: Arc::new(Box::new(router(format!("command{}", i)))),
+                callback: Arc::new(callback),
+            })
+            .collect(),
+    )
+}
+
+fn dispatch(c: &mut Criterion) {
+    let indexed = handler(|command| CommandRouter::command([command]));
+    let linear = handler(|command| OpaqueRouter(CommandRouter::command([command])));
+    let event: Arc<Box<dyn Event>> = Arc::new(Box::new(format!("/command{} arg", COMMANDS - 1)));
+
+    let mut group = c.benchmark_group("dispatch");
+    group.bench_function("indexed", |b| {
+        b.iter(|| block_on(indexed.matches(black_box(event.clone()))).len())
+    });
+    group.bench_function("linear", |b| {
+        b.iter(|| block_on(linear.matches(black_box(event.clone()))).len())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
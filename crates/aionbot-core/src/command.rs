@@ -0,0 +1,704 @@
+use std::{any::Any, fmt::Write, future::Future, pin::Pin, sync::Arc};
+
+use anyhow::Result;
+
+use crate::{
+    entry::{intern_id, Entry},
+    event::{Content, Event},
+    identity::{Channel, User},
+    message::Message,
+    router::{CommandRouter, CommandSet, Cost, Descriptor, MatchContext, Router},
+    types::{CommandCallback, HandlerCallback},
+};
+
+/// Split text into arguments by whitespace, double quotes group an argument.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut pending = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                pending = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if pending {
+                    tokens.push(std::mem::take(&mut token));
+                    pending = false;
+                }
+            }
+            c => {
+                token.push(c);
+                pending = true;
+            }
+        }
+    }
+    if pending {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Declared argument of a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Arg {
+    pub name: String,
+    pub required: bool,
+}
+
+/// Arguments passed to a command callback.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandArgs {
+    path: Vec<String>,
+    names: Vec<String>,
+    values: Vec<String>,
+}
+
+impl CommandArgs {
+    /// Get the names of the invoked command, e.g. `["config", "set"]`.
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// Get the value of a declared argument.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .and_then(|i| self.values.get(i))
+            .map(String::as_str)
+    }
+
+    /// Get all argument values in order.
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    /// Get the values after the declared arguments.
+    pub fn rest(&self) -> &[String] {
+        &self.values[self.names.len().min(self.values.len())..]
+    }
+}
+
+/// Sub-command of a [`CommandGroup`].
+pub struct Command {
+    name: String,
+    args: Vec<Arg>,
+    help: Option<String>,
+    callback: Arc<CommandCallback>,
+}
+
+impl Command {
+    pub fn new<S, F>(name: S, callback: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(Arc<Box<dyn Event>>, CommandArgs) -> HandlerCallback + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            args: vec![],
+            help: None,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Declare a required argument.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an optional argument is already declared.
+    pub fn arg<S: Into<String>>(mut self, name: S) -> Self {
+        let name = name.into();
+        if self.args.last().is_some_and(|arg| !arg.required) {
+            panic!(
+                "Required argument [{}] of command [{}] follows an optional one",
+                name, self.name
+            );
+        }
+        self.args.push(Arg {
+            name,
+            required: true,
+        });
+        self
+    }
+
+    /// Declare an optional argument, must follow the required ones.
+    pub fn optional<S: Into<String>>(mut self, name: S) -> Self {
+        self.args.push(Arg {
+            name: name.into(),
+            required: false,
+        });
+        self
+    }
+
+    pub fn help<S: Into<String>>(mut self, help: S) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn args(&self) -> &[Arg] {
+        &self.args
+    }
+
+    /// Get the usage of the command, e.g. `set <key> [value]`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.clone();
+        for arg in &self.args {
+            if arg.required {
+                write!(usage, " <{}>", arg.name).unwrap();
+            } else {
+                write!(usage, " [{}]", arg.name).unwrap();
+            }
+        }
+        usage
+    }
+}
+
+/// Command dispatching to sub-commands and nested groups, e.g. `/config set`.
+///
+/// Without a fallback, the group replies its help when no or an unknown
+/// sub-command is given.
+pub struct CommandGroup {
+    name: String,
+    prefixes: Option<Vec<String>>,
+    help: Option<String>,
+    priority: i8,
+    commands: Vec<Command>,
+    groups: Vec<CommandGroup>,
+    fallback: Option<Arc<CommandCallback>>,
+}
+
+impl CommandGroup {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            prefixes: None,
+            help: None,
+            priority: 0,
+            commands: vec![],
+            groups: vec![],
+            fallback: None,
+        }
+    }
+
+    /// Set the command prefixes instead of the prefixes of the handler, only
+    /// used by the root group.
+    pub fn prefixes<S: Into<String>, I: IntoIterator<Item = S>>(mut self, prefixes: I) -> Self {
+        self.prefixes = Some(prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn help<S: Into<String>>(mut self, help: S) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Set the priority of the entry, only used by the root group.
+    pub fn priority(mut self, priority: i8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn command(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    pub fn group(mut self, group: CommandGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    /// Handle missing or unknown sub-commands, the arguments start with the
+    /// unknown sub-command if any.
+    pub fn fallback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(Arc<Box<dyn Event>>, CommandArgs) -> HandlerCallback + Send + Sync + 'static,
+    {
+        self.fallback = Some(Arc::new(callback));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Render the help of the group and its sub-commands.
+    pub fn help_text(&self) -> String {
+        self.render_help(&self.prefix(None), std::slice::from_ref(&self.name))
+    }
+
+    /// Get the prefix shown in help, the first prefix in the channel.
+    fn prefix(&self, channel_id: Option<&str>) -> String {
+        match &self.prefixes {
+            Some(prefixes) => prefixes.first().cloned(),
+            None => MatchContext::current()
+                .prefixes
+                .resolve(channel_id)
+                .first()
+                .cloned(),
+        }
+        .unwrap_or_default()
+    }
+
+    fn router(&self) -> CommandRouter {
+        let mut router = CommandRouter::command([self.name.clone()]);
+        router.prefixes = self.prefixes.clone();
+        router
+    }
+
+    fn render_help(&self, prefix: &str, path: &[String]) -> String {
+        let mut help = String::new();
+        self.write_help(&mut help, &format!("{}{}", prefix, path.join(" ")), 0);
+        help.trim_end().to_string()
+    }
+
+    fn write_help(&self, help: &mut String, path: &str, depth: usize) {
+        let indent = "  ".repeat(depth);
+        match &self.help {
+            Some(text) => writeln!(help, "{}{} - {}", indent, path, text).unwrap(),
+            None => writeln!(help, "{}{}", indent, path).unwrap(),
+        }
+        for command in &self.commands {
+            let usage = format!("{}{} {}", indent, path, command.usage());
+            match &command.help {
+                Some(text) => writeln!(help, "  {} - {}", usage, text).unwrap(),
+                None => writeln!(help, "  {}", usage).unwrap(),
+            }
+        }
+        for group in &self.groups {
+            group.write_help(help, &format!("{} {}", path, group.name), depth + 1);
+        }
+    }
+
+    fn dispatch(
+        &self,
+        event: Arc<Box<dyn Event>>,
+        prefix: &str,
+        mut path: Vec<String>,
+        tokens: &[String],
+    ) -> HandlerCallback {
+        let Some((name, rest)) = tokens.split_first() else {
+            return self.fallback_or_help(event, prefix, path, tokens);
+        };
+        if let Some(group) = self.groups.iter().find(|g| &g.name == name) {
+            path.push(name.clone());
+            return group.dispatch(event, prefix, path, rest);
+        }
+        let Some(command) = self.commands.iter().find(|c| &c.name == name) else {
+            return self.fallback_or_help(event, prefix, path, tokens);
+        };
+        let required = command.args.iter().filter(|a| a.required).count();
+        if rest.len() < required {
+            let usage = format!("Usage: {}{} {}", prefix, path.join(" "), command.usage());
+            return reply(event, usage);
+        }
+        path.push(name.clone());
+        let args = CommandArgs {
+            path,
+            names: command.args.iter().map(|a| a.name.clone()).collect(),
+            values: rest.to_vec(),
+        };
+        (command.callback)(event, args)
+    }
+
+    fn fallback_or_help(
+        &self,
+        event: Arc<Box<dyn Event>>,
+        prefix: &str,
+        path: Vec<String>,
+        tokens: &[String],
+    ) -> HandlerCallback {
+        match &self.fallback {
+            Some(fallback) => fallback(
+                event,
+                CommandArgs {
+                    path,
+                    names: vec![],
+                    values: tokens.to_vec(),
+                },
+            ),
+            None => {
+                let help = self.render_help(prefix, &path);
+                reply(event, help)
+            }
+        }
+    }
+
+    /// Build the handler entry dispatching the group.
+    pub fn into_entry(self) -> Entry {
+        let router = Arc::new(self.router());
+        let id = intern_id(format!("command:{}", self.name));
+        let priority = self.priority;
+        let group_router = router.clone();
+        let group = Arc::new(self);
+        Entry {
+            id,
+            priority,
+            router: Arc::new(Box::new(SharedRouter(router))),
+            callback: Arc::new(move |event| {
+                let channel_id = event.channel_id();
+                let tokens = event
+                    .plain_text()
+                    .and_then(|text| group_router.parse_in(text, channel_id))
+                    .map(|(_, rest)| tokenize(rest))
+                    .unwrap_or_default();
+                let prefix = group.prefix(channel_id);
+                group.dispatch(event, &prefix, vec![group.name.clone()], &tokens)
+            }),
+        }
+    }
+}
+
+/// Router of a group entry, shared with the callback parsing its arguments.
+struct SharedRouter(Arc<CommandRouter>);
+
+impl Router for SharedRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.0.matches(event)
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        self.0.descriptor()
+    }
+
+    fn cost(&self) -> Cost {
+        self.0.cost()
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.0.commands()
+    }
+}
+
+impl From<CommandGroup> for Entry {
+    fn from(group: CommandGroup) -> Self {
+        group.into_entry()
+    }
+}
+
+/// Get the edit distance between two strings by characters, counting
+/// insertions, deletions, substitutions and adjacent transpositions.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Get the commands similar to the name, closest first.
+pub fn suggest<'a, I: IntoIterator<Item = &'a str>>(name: &str, commands: I) -> Vec<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut suggestions = commands
+        .into_iter()
+        .map(|command| (edit_distance(name, command), command))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    suggestions.sort();
+    suggestions.dedup();
+    suggestions
+        .into_iter()
+        .take(3)
+        .map(|(_, command)| command)
+        .collect()
+}
+
+/// Event emitted when a message starts with a command prefix but no
+/// command matches it.
+pub struct UnknownCommand {
+    /// Prefix the message starts with.
+    pub prefix: String,
+    /// Name of the unknown command.
+    pub command: String,
+    /// Registered commands similar to the unknown command.
+    pub suggestions: Vec<String>,
+    /// The original message event.
+    pub event: Arc<Box<dyn Event>>,
+}
+
+impl UnknownCommand {
+    /// Parse an unknown command from the event with the prefixes of the
+    /// handler or the own prefixes of the registered commands, suggesting the
+    /// commands sharing the prefix.
+    pub fn parse<'a, I>(event: Arc<Box<dyn Event>>, commands: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = CommandSet<'a>>,
+    {
+        let text = event.plain_text()?;
+        let context = MatchContext::current();
+        let default = context.prefixes.resolve(event.channel_id());
+        let commands = commands
+            .into_iter()
+            .map(|set| (set.prefixes.unwrap_or(&default), set.names))
+            .collect::<Vec<_>>();
+        let (prefix, rest) = default
+            .iter()
+            .chain(commands.iter().flat_map(|(prefixes, _)| prefixes.iter()))
+            .filter(|prefix| !prefix.is_empty())
+            .filter_map(|prefix| Some((prefix.clone(), text.strip_prefix(prefix.as_str())?)))
+            .max_by_key(|(prefix, _)| prefix.len())?;
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '@')
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return None;
+        }
+        let command = rest[..end].to_string();
+        let names = commands
+            .iter()
+            .filter(|(prefixes, _)| prefixes.contains(&prefix))
+            .flat_map(|(_, names)| names.iter().copied());
+        let suggestions = suggest(&command, names)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        Some(Self {
+            prefix,
+            command,
+            suggestions,
+            event,
+        })
+    }
+
+    /// Get the reply suggesting similar commands, if there are any.
+    pub fn suggestion_text(&self) -> Option<String> {
+        if self.suggestions.is_empty() {
+            return None;
+        }
+        let suggestions = self
+            .suggestions
+            .iter()
+            .map(|s| format!("{}{}", self.prefix, s))
+            .collect::<Vec<_>>();
+        Some(format!(
+            "Unknown command {}{}, did you mean {}?",
+            self.prefix,
+            self.command,
+            suggestions.join(", ")
+        ))
+    }
+}
+
+impl Event for UnknownCommand {
+    fn name(&self) -> &str {
+        "unknown_command"
+    }
+
+    fn event_type(&self) -> &str {
+        "unknown_command"
+    }
+
+    fn adapter(&self) -> &str {
+        self.event.adapter()
+    }
+
+    fn content(&self) -> Option<Content<'_>> {
+        Some(Content::Any(self))
+    }
+
+    fn sender(&self) -> Option<&User> {
+        self.event.sender()
+    }
+
+    fn channel(&self) -> Option<&Channel> {
+        self.event.channel()
+    }
+
+    fn is_to_me(&self) -> bool {
+        self.event.is_to_me()
+    }
+
+    fn reply<'s, 'a>(
+        &'s self,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>
+    where
+        Self: 'a,
+        's: 'a,
+    {
+        self.event.reply(message)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn reply(event: Arc<Box<dyn Event>>, text: String) -> HandlerCallback {
+    Box::pin(async move { event.reply(Message::from(text)).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    type Calls = Arc<Mutex<Vec<CommandArgs>>>;
+
+    fn record(calls: &Calls) -> impl Fn(Arc<Box<dyn Event>>, CommandArgs) -> HandlerCallback {
+        let calls = calls.clone();
+        move |_event, args| {
+            calls.lock().unwrap().push(args);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn config(calls: &Calls, fallback: &Calls) -> CommandGroup {
+        CommandGroup::new("config")
+            .help("Manage configuration")
+            .command(
+                Command::new("set", record(calls))
+                    .arg("key")
+                    .arg("value")
+                    .help("Set a value"),
+            )
+            .command(Command::new("get", record(calls)).arg("key"))
+            .group(
+                CommandGroup::new("user")
+                    .command(Command::new("list", record(calls)).optional("page")),
+            )
+            .fallback(record(fallback))
+    }
+
+    fn run(entry: &Entry, text: &str) -> anyhow::Result<()> {
+        let event: Arc<Box<dyn Event>> = Arc::new(Box::new(text.to_string()));
+        assert!(entry.get_router().matches(&**event));
+        block_on(entry.get_handler()(event))
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(" set  key \"a value\" "),
+            ["set", "key", "a value"]
+        );
+        assert_eq!(tokenize("\"\" x"), ["", "x"]);
+        assert!(tokenize("  ").is_empty());
+    }
+
+    #[test]
+    fn test_command_group() {
+        let calls = Calls::default();
+        let fallback = Calls::default();
+        let entry = config(&calls, &fallback).into_entry();
+
+        run(&entry, "/config set key \"a value\" extra").unwrap();
+        run(&entry, "/config user list").unwrap();
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls[0].path(), ["config", "set"]);
+        assert_eq!(calls[0].get("key"), Some("key"));
+        assert_eq!(calls[0].get("value"), Some("a value"));
+        assert_eq!(calls[0].rest(), ["extra"]);
+        assert_eq!(calls[1].path(), ["config", "user", "list"]);
+        assert_eq!(calls[1].get("page"), None);
+
+        run(&entry, "/config unset key").unwrap();
+        run(&entry, "/config").unwrap();
+        let fallback = fallback.lock().unwrap();
+        assert_eq!(fallback[0].path(), ["config"]);
+        assert_eq!(fallback[0].values(), ["unset", "key"]);
+        assert!(fallback[1].values().is_empty());
+    }
+
+    #[test]
+    fn test_suggestions() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("帮助", "帮"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("hlep", "help"), 1);
+        let commands = ["help", "hello", "config", "list"];
+        assert_eq!(suggest("helo", commands), ["hello", "help"]);
+        assert_eq!(suggest("conifg", commands), ["config"]);
+        assert!(suggest("xyz", commands).is_empty());
+
+        let event: Arc<Box<dyn Event>> = Arc::new(Box::new("/helo@bot world".to_string()));
+        let prefixes = ["!".to_string()];
+        let sets = [
+            CommandSet {
+                prefixes: None,
+                names: commands.to_vec(),
+            },
+            CommandSet {
+                prefixes: Some(&prefixes),
+                names: vec!["helm"],
+            },
+        ];
+        let unknown = UnknownCommand::parse(event, sets.clone()).unwrap();
+        assert_eq!(unknown.command, "helo");
+        assert_eq!(
+            unknown.suggestion_text().unwrap(),
+            "Unknown command /helo, did you mean /hello, /help?"
+        );
+        let event: Arc<Box<dyn Event>> = Arc::new(Box::new("!helo".to_string()));
+        let unknown = UnknownCommand::parse(event, sets.clone()).unwrap();
+        assert_eq!(unknown.prefix, "!");
+        assert_eq!(unknown.suggestions, ["helm"]);
+        let event: Arc<Box<dyn Event>> = Arc::new(Box::new("hello".to_string()));
+        assert!(UnknownCommand::parse(event, sets).is_none());
+    }
+
+    #[test]
+    fn test_command_group_help() {
+        let calls = Calls::default();
+        let group = CommandGroup::new("config")
+            .help("Manage configuration")
+            .command(
+                Command::new("get", record(&calls))
+                    .arg("key")
+                    .help("Get a value"),
+            )
+            .group(
+                CommandGroup::new("user")
+                    .command(Command::new("list", record(&calls)).optional("page")),
+            );
+        assert_eq!(
+            group.help_text(),
+            "/config - Manage configuration\n  \
+             /config get <key> - Get a value\n  \
+             /config user\n    \
+             /config user list [page]"
+        );
+
+        // Events without reply support fail to reply the help or usage.
+        let entry = group.into_entry();
+        // Entries of groups with the same name share their ID.
+        assert!(std::ptr::eq(
+            entry.id,
+            CommandGroup::new("config").into_entry().id
+        ));
+        assert!(run(&entry, "/config unknown").is_err());
+        assert!(run(&entry, "/config get").is_err());
+        assert!(calls.lock().unwrap().is_empty());
+    }
+    #[test]
+    #[should_panic(expected = "Required argument [key] of command [get] follows an optional one")]
+    fn test_required_after_optional() {
+        let calls = Calls::default();
+        let _ = Command::new("get", record(&calls))
+            .optional("page")
+            .arg("key");
+    }
+}
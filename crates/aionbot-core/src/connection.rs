@@ -0,0 +1,117 @@
+use std::{any::Any, fmt};
+
+use crate::event::{Content, Event};
+
+/// Reason why a bot has been disconnected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The remote side closed the connection.
+    Closed,
+    /// A new connection of the same bot replaced this one.
+    Replaced,
+    /// The bot stopped responding in time.
+    Timeout,
+    /// The runtime closed the connection.
+    Shutdown,
+    /// The connection failed with an error.
+    Error(String),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => f.write_str("connection closed by remote"),
+            Self::Replaced => f.write_str("connection replaced"),
+            Self::Timeout => f.write_str("connection timed out"),
+            Self::Shutdown => f.write_str("connection closed by runtime"),
+            Self::Error(e) => write!(f, "connection error: {}", e),
+        }
+    }
+}
+
+/// Lifecycle event emitted by adapters when a bot connects or drops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connected {
+        adapter: &'static str,
+        self_id: String,
+    },
+    Disconnected {
+        adapter: &'static str,
+        self_id: String,
+        reason: DisconnectReason,
+    },
+}
+
+impl ConnectionEvent {
+    pub fn connected<S: Into<String>>(adapter: &'static str, self_id: S) -> Self {
+        Self::Connected {
+            adapter,
+            self_id: self_id.into(),
+        }
+    }
+
+    pub fn disconnected<S: Into<String>>(
+        adapter: &'static str,
+        self_id: S,
+        reason: DisconnectReason,
+    ) -> Self {
+        Self::Disconnected {
+            adapter,
+            self_id: self_id.into(),
+            reason,
+        }
+    }
+
+    pub fn adapter(&self) -> &'static str {
+        match self {
+            Self::Connected { adapter, .. } | Self::Disconnected { adapter, .. } => adapter,
+        }
+    }
+
+    pub fn self_id(&self) -> &str {
+        match self {
+            Self::Connected { self_id, .. } | Self::Disconnected { self_id, .. } => self_id,
+        }
+    }
+
+    pub fn reason(&self) -> Option<&DisconnectReason> {
+        match self {
+            Self::Connected { .. } => None,
+            Self::Disconnected { reason, .. } => Some(reason),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Self::Connected { .. })
+    }
+}
+
+impl Event for ConnectionEvent {
+    fn name(&self) -> &str {
+        "connection"
+    }
+
+    fn event_type(&self) -> &str {
+        match self {
+            Self::Connected { .. } => "bot_connected",
+            Self::Disconnected { .. } => "bot_disconnected",
+        }
+    }
+
+    fn adapter(&self) -> &str {
+        ConnectionEvent::adapter(self)
+    }
+
+    fn content(&self) -> Option<Content<'_>> {
+        Some(Content::Any(self))
+    }
+
+    fn plain_data(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,4 +1,8 @@
-use std::{hash::Hash, sync::Arc};
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    sync::{Arc, LazyLock, Mutex},
+};
 
 use crate::{router::Router, types::Callback};
 
@@ -10,6 +14,21 @@ pub struct Entry {
     pub callback: Arc<Callback>,
 }
 
+/// IDs of entries built at runtime.
+static IDS: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Mutex::default);
+
+/// Get the static ID of an entry built at runtime, each distinct ID is only
+/// allocated once.
+pub(crate) fn intern_id(id: String) -> &'static str {
+    let mut ids = IDS.lock().unwrap();
+    if let Some(id) = ids.get(id.as_str()) {
+        return id;
+    }
+    let id = Box::leak(id.into_boxed_str());
+    ids.insert(id);
+    id
+}
+
 impl Entry {
     pub fn get_priority(&self) -> i8 {
         self.priority
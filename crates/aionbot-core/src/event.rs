@@ -1,6 +1,102 @@
-use std::{any::Any, future::Future, pin::Pin};
+use std::{any::Any, fmt, future::Future, pin::Pin};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+
+use crate::{
+    identity::{Channel, User},
+    message::Message,
+};
+
+/// Content of an event borrowed from the event itself.
+#[derive(Clone, Copy, Debug)]
+pub enum Content<'a> {
+    /// Plain text content, e.g. text of a chat message.
+    Text(&'a str),
+    /// Structured content of any other type.
+    Any(&'a dyn Any),
+}
+
+impl<'a> Content<'a> {
+    pub fn as_text(&self) -> Option<&'a str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Any(_) => None,
+        }
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&'a T> {
+        match self {
+            Self::Text(_) => None,
+            Self::Any(content) => content.downcast_ref::<T>(),
+        }
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.downcast_ref::<T>().is_some()
+    }
+}
+
+/// Error raised when an event does not support an operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventError {
+    Unsupported {
+        event_type: String,
+        feature: &'static str,
+    },
+    Mismatch {
+        event_type: String,
+        expected: &'static str,
+    },
+}
+
+impl EventError {
+    pub fn unsupported(event: &(impl Event + ?Sized), feature: &'static str) -> Self {
+        Self::Unsupported {
+            event_type: event.event_type().to_string(),
+            feature,
+        }
+    }
+
+    pub fn mismatch<T: Event>(event: &(impl Event + ?Sized)) -> Self {
+        Self::Mismatch {
+            event_type: event.event_type().to_string(),
+            expected: std::any::type_name::<T>(),
+        }
+    }
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported {
+                event_type,
+                feature,
+            } => write!(
+                f,
+                "Event of type [{}] does not support {}.",
+                event_type, feature
+            ),
+            Self::Mismatch {
+                event_type,
+                expected,
+            } => write!(
+                f,
+                "Event of type [{}] is not an instance of [{}].",
+                event_type, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EventError {}
+
+/// Features supported by an event.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub can_reply: bool,
+    pub has_channel: bool,
+    pub has_sender: bool,
+}
 
 pub trait Event: Any + Send + Sync {
     /// Get the name of the event.
@@ -9,51 +105,94 @@ pub trait Event: Any + Send + Sync {
     }
     /// Get the type of the event.
     fn event_type(&self) -> &str;
+    /// Get the name of the adapter which emits the event.
+    fn adapter(&self) -> &str {
+        "unknown_adapter"
+    }
     /// Get the deserialized content of the event.
-    fn content(&self) -> Box<dyn Any> {
-        unimplemented!()
+    fn content(&self) -> Option<Content<'_>> {
+        None
     }
     /// Get the plain data of the event.
-    fn plain_data(&self) -> Box<dyn Any> {
-        unimplemented!()
+    fn plain_data(&self) -> Option<&dyn Any> {
+        None
+    }
+    /// Get the user who emitted the event.
+    fn sender(&self) -> Option<&User> {
+        None
+    }
+    /// Get the channel where the event comes from.
+    fn channel(&self) -> Option<&Channel> {
+        None
     }
     /// Get the emitter ID of the event.
-    fn emitter_id(&self) -> &str {
-        unimplemented!()
+    fn emitter_id(&self) -> Option<&str> {
+        self.sender().map(|sender| sender.id.as_str())
     }
-    /// Get the channel ID of the event.
-    fn channel_id(&self) -> Result<&str> {
-        unimplemented!()
+    /// Get the channel ID of the event, `None` for private channels.
+    fn channel_id(&self) -> Option<&str> {
+        self.channel()
+            .filter(|channel| !channel.is_private())
+            .map(Channel::id)
+    }
+    /// Check whether the event is addressed to the bot, e.g. a private
+    /// message or a message mentioning the bot.
+    ///
+    /// Adapters should strip the leading mention or bot name from the text
+    /// content of such events.
+    fn is_to_me(&self) -> bool {
+        false
+    }
+    /// Get the IDs of users mentioned in the event.
+    fn mentions(&self) -> &[String] {
+        &[]
     }
     /// Get the plain text of the event.
-    fn plain_text(&self) -> Result<&str> {
-        if let Ok(plain_text) = self.content().downcast::<&str>() {
-            Ok(*plain_text)
-        } else {
-            Err(anyhow!(
-                "Failed to downcast plain data to [&str], \
-            this is most likely a AionBot internal bug."
-            ))
+    fn plain_text(&self) -> Option<&str> {
+        self.content()?.as_text()
+    }
+    /// Get the features supported by the event.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            can_reply: false,
+            has_channel: self.channel_id().is_some(),
+            has_sender: self.emitter_id().is_some(),
         }
     }
     /// Quickly reply back to the channel.
     fn reply<'s, 'a>(
         &'s self,
-        message: Box<dyn ToString + Send + Sync>,
+        message: Message,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>
     where
         Self: 'a,
         's: 'a,
     {
-        unimplemented!(
-            "Failed to quick reply message {} back to the channel, \
-            perhaps the bot runtime does not support this feature.",
-            message.to_string()
-        )
+        let error = EventError::unsupported(self, "reply");
+        log::debug!("Failed to reply message [{}]: {}", message, error);
+        Box::pin(async move { Err(error.into()) })
     }
     fn as_any(&self) -> &dyn Any;
 }
 
+impl dyn Event {
+    /// Check whether the event is of concrete type `T`.
+    pub fn is<T: Event>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    /// Downcast the event to concrete type `T`.
+    pub fn downcast_ref<T: Event>(&self) -> Option<&T> {
+        self.as_any().downcast_ref::<T>()
+    }
+
+    /// Downcast the event to concrete type `T`, or return a mismatch error.
+    pub fn try_downcast_ref<T: Event>(&self) -> Result<&T, EventError> {
+        self.downcast_ref::<T>()
+            .ok_or_else(|| EventError::mismatch::<T>(self))
+    }
+}
+
 impl Event for String {
     fn event_type(&self) -> &str {
         "string_event"
@@ -63,8 +202,53 @@ impl Event for String {
         self
     }
 
-    fn content(&self) -> Box<dyn Any> {
-        let str: &str = self.clone().leak();
-        Box::new(str)
+    fn content(&self) -> Option<Content<'_>> {
+        Some(Content::Text(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MinimalEvent;
+
+    impl Event for MinimalEvent {
+        fn event_type(&self) -> &str {
+            "minimal_event"
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_default_event_methods() {
+        let event = MinimalEvent;
+        assert!(event.content().is_none());
+        assert!(event.plain_text().is_none());
+        assert!(event.emitter_id().is_none());
+        assert!(event.channel_id().is_none());
+        assert_eq!(event.capabilities(), Capabilities::default());
+
+        let error = futures::executor::block_on(event.reply("hello".into())).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<EventError>(),
+            Some(&EventError::unsupported(&event, "reply"))
+        );
+    }
+
+    #[test]
+    fn test_downcast_event() {
+        let event: Box<dyn Event> = Box::new("hello".to_string());
+        assert!(event.is::<String>());
+        assert!(!event.is::<MinimalEvent>());
+        assert_eq!(event.downcast_ref::<String>().unwrap(), "hello");
+        assert!(event.downcast_ref::<MinimalEvent>().is_none());
+        assert_eq!(
+            event.try_downcast_ref::<MinimalEvent>().err(),
+            Some(EventError::mismatch::<MinimalEvent>(&*event))
+        );
     }
 }
@@ -0,0 +1,593 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{
+    entry::{intern_id, Entry},
+    event::Event,
+    router::{CommandSet, Router},
+    runtime::StateManager,
+    types::{Callback, FlowCallback, FlowFuture, HandlerCallback},
+};
+
+/// Next step of a flow returned by state handlers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transition {
+    /// Move to the named state.
+    Goto(String),
+    /// Stay in the current state, e.g. to ask again on invalid input.
+    Stay,
+    /// End the flow.
+    Finish,
+}
+
+impl Transition {
+    pub fn goto<S: Into<String>>(state: S) -> Self {
+        Self::Goto(state.into())
+    }
+}
+
+/// Who shares the progress of a flow, private chats are channels of their own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlowScope {
+    /// Each user has their own progress in each channel.
+    #[default]
+    User,
+    /// Each user has one progress across channels.
+    Global,
+    /// All users in a channel share one progress.
+    Channel,
+}
+
+impl FlowScope {
+    fn key(&self, event: &dyn Event) -> Option<String> {
+        let channel = || event.channel().map(ToString::to_string);
+        match self {
+            Self::User => Some(format!(
+                "{}/{}",
+                channel().unwrap_or_default(),
+                event.emitter_id()?
+            )),
+            Self::Global => event.emitter_id().map(String::from),
+            Self::Channel => channel(),
+        }
+    }
+}
+
+/// Progress of a running flow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowProgress {
+    pub state: String,
+    pub data: HashMap<String, String>,
+    pub deadline: Option<Instant>,
+}
+
+impl FlowProgress {
+    fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+    }
+}
+
+/// Name of a flow and the key of its scope.
+type FlowKey = (String, String);
+
+/// Storage of flow progress, kept in the [`StateManager`].
+///
+/// Expired progress is dropped whenever progress is saved.
+#[derive(Default)]
+pub struct FlowStore {
+    progress: Mutex<HashMap<FlowKey, FlowProgress>>,
+    /// Locks serializing the steps of each flow and key.
+    steps: Mutex<HashMap<FlowKey, Weak<tokio::sync::Mutex<()>>>>,
+    names: Mutex<HashSet<String>>,
+}
+
+impl FlowStore {
+    /// Get the unexpired progress of the flow for the key.
+    pub fn get(&self, flow: &str, key: &str) -> Option<FlowProgress> {
+        let mut progress = self.progress.lock().unwrap();
+        let id = (flow.to_string(), key.to_string());
+        if progress.get(&id)?.is_expired() {
+            progress.remove(&id);
+            return None;
+        }
+        progress.get(&id).cloned()
+    }
+
+    pub fn set(&self, flow: &str, key: &str, progress: FlowProgress) {
+        let mut all = self.progress.lock().unwrap();
+        all.retain(|_, progress| !progress.is_expired());
+        all.insert((flow.to_string(), key.to_string()), progress);
+    }
+
+    /// Remove the progress of the flow for the key, returns whether it existed.
+    pub fn remove(&self, flow: &str, key: &str) -> bool {
+        self.progress
+            .lock()
+            .unwrap()
+            .remove(&(flow.to_string(), key.to_string()))
+            .is_some()
+    }
+
+    /// Get the lock held while a step of the flow for the key runs.
+    fn step_lock(&self, flow: &str, key: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut steps = self.steps.lock().unwrap();
+        let id = (flow.to_string(), key.to_string());
+        if let Some(lock) = steps.get(&id).and_then(Weak::upgrade) {
+            return lock;
+        }
+        steps.retain(|_, lock| lock.strong_count() > 0);
+        let lock = Arc::default();
+        steps.insert(id, Arc::downgrade(&lock));
+        lock
+    }
+}
+
+/// Context passed to state handlers.
+#[derive(Clone)]
+pub struct FlowContext {
+    state: String,
+    data: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl FlowContext {
+    /// Get the name of the current state.
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    /// Get a value collected by previous states.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.data.lock().unwrap().get(key).cloned()
+    }
+
+    /// Store a value for the following states.
+    pub fn set<K: Into<String>, V: Into<String>>(&self, key: K, value: V) {
+        self.data.lock().unwrap().insert(key.into(), value.into());
+    }
+
+    pub fn remove(&self, key: &str) -> Option<String> {
+        self.data.lock().unwrap().remove(key)
+    }
+
+    /// Get all collected values.
+    pub fn data(&self) -> HashMap<String, String> {
+        self.data.lock().unwrap().clone()
+    }
+}
+
+/// Named state of a [`Flow`].
+pub struct FlowState {
+    name: String,
+    router: Option<Box<dyn Router>>,
+    callback: Arc<FlowCallback>,
+}
+
+impl FlowState {
+    pub fn new<S, F>(name: S, callback: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(Arc<Box<dyn Event>>, FlowContext) -> FlowFuture + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            router: None,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Only handle events matched by the router in this state, other events
+    /// are dispatched as usual.
+    pub fn router<R: Router + 'static>(mut self, router: R) -> Self {
+        self.router = Some(Box::new(router));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Multi-step conversation as a finite-state machine.
+///
+/// The trigger starts the flow in the first state, each state handler then
+/// decides the next state with a [`Transition`].
+pub struct Flow {
+    name: String,
+    trigger: Box<dyn Router>,
+    states: Vec<FlowState>,
+    scope: FlowScope,
+    timeout: Duration,
+    cancel: Option<Box<dyn Router>>,
+    on_cancel: Option<Arc<Callback>>,
+    priority: i8,
+}
+
+impl Flow {
+    pub fn new<S: Into<String>, R: Router + 'static>(name: S, trigger: R) -> Self {
+        Self {
+            name: name.into(),
+            trigger: Box::new(trigger),
+            states: vec![],
+            scope: FlowScope::default(),
+            timeout: Duration::from_secs(600),
+            cancel: None,
+            on_cancel: None,
+            priority: 0,
+        }
+    }
+
+    /// Add a state, the first state is entered when the flow starts.
+    pub fn state(mut self, state: FlowState) -> Self {
+        self.states.push(state);
+        self
+    }
+
+    pub fn scope(mut self, scope: FlowScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Drop the progress if no event advances the flow within the timeout,
+    /// defaults to 10 minutes.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// End the flow when the router matches an event of a running flow.
+    pub fn cancel<R: Router + 'static>(mut self, router: R) -> Self {
+        self.cancel = Some(Box::new(router));
+        self
+    }
+
+    pub fn on_cancel<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(Arc<Box<dyn Event>>) -> HandlerCallback + Send + Sync + 'static,
+    {
+        self.on_cancel = Some(Arc::new(callback));
+        self
+    }
+
+    pub fn priority(mut self, priority: i8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn find_state(&self, name: &str) -> Option<&FlowState> {
+        self.states.iter().find(|state| state.name == name)
+    }
+
+    /// Whether any router of the flow needs to await.
+    fn is_async(&self) -> bool {
+        self.trigger.is_async()
+            || self.cancel.as_ref().is_some_and(|r| r.is_async())
+            || self
+                .states
+                .iter()
+                .any(|state| state.router.as_ref().is_some_and(|r| r.is_async()))
+    }
+
+    async fn is_cancel(&self, event: &dyn Event) -> bool {
+        match &self.cancel {
+            Some(cancel) => cancel.matches_async(event).await,
+            None => false,
+        }
+    }
+
+    async fn matches(&self, event: &dyn Event, store: &FlowStore) -> bool {
+        let Some(key) = self.scope.key(event) else {
+            return false;
+        };
+        let Some(progress) = store.get(&self.name, &key) else {
+            return self.trigger.matches_async(event).await;
+        };
+        if self.is_cancel(event).await {
+            return true;
+        }
+        match self.find_state(&progress.state).map(|state| &state.router) {
+            Some(Some(router)) => router.matches_async(event).await,
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    async fn handle(&self, event: Arc<Box<dyn Event>>, store: &FlowStore) -> Result<()> {
+        let Some(key) = self.scope.key(&**event) else {
+            return Ok(());
+        };
+        // Events of the same key run one step at a time, so each step sees
+        // the progress saved by the previous one.
+        let lock = store.step_lock(&self.name, &key);
+        let _step = lock.lock().await;
+        let (progress, resumed) = match store.get(&self.name, &key) {
+            Some(progress) => {
+                if self.is_cancel(&**event).await {
+                    store.remove(&self.name, &key);
+                    if let Some(on_cancel) = &self.on_cancel {
+                        on_cancel(event).await?;
+                    }
+                    return Ok(());
+                }
+                (progress, true)
+            }
+            None if self.trigger.matches_async(&**event).await => match self.states.first() {
+                Some(state) => {
+                    let progress = FlowProgress {
+                        state: state.name.clone(),
+                        data: HashMap::new(),
+                        deadline: None,
+                    };
+                    (progress, false)
+                }
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        let Some(state) = self.find_state(&progress.state) else {
+            log::warn!("Flow [{}] has no state [{}]", self.name, progress.state);
+            store.remove(&self.name, &key);
+            return Ok(());
+        };
+        // The event was matched before the previous step finished, which may
+        // have moved the flow to a state not expecting it.
+        if let Some(router) = state.router.as_ref().filter(|_| resumed) {
+            if !router.matches_async(&**event).await {
+                return Ok(());
+            }
+        }
+        let context = FlowContext {
+            state: progress.state,
+            data: Arc::new(Mutex::new(progress.data)),
+        };
+        let next = match (state.callback)(event, context.clone()).await? {
+            Transition::Goto(next) => next,
+            Transition::Stay => context.state.clone(),
+            Transition::Finish => {
+                store.remove(&self.name, &key);
+                return Ok(());
+            }
+        };
+        store.set(
+            &self.name,
+            &key,
+            FlowProgress {
+                state: next,
+                data: context.data(),
+                deadline: Instant::now().checked_add(self.timeout),
+            },
+        );
+        Ok(())
+    }
+
+    /// Build the handler entry of the flow, storing progress in the state.
+    ///
+    /// Fails if a flow with the same name is already registered in the state.
+    pub fn into_entry(self, state: Arc<StateManager>) -> Result<Entry> {
+        if state.try_get::<FlowStore>().is_none() {
+            state.set(FlowStore::default());
+        }
+        let names = &state.get::<FlowStore>().names;
+        if !names.lock().unwrap().insert(self.name.clone()) {
+            bail!("Flow [{}] is already registered", self.name);
+        }
+        let id = intern_id(format!("flow:{}", self.name));
+        let priority = self.priority;
+        let flow = Arc::new(self);
+        Ok(Entry {
+            id,
+            priority,
+            router: Arc::new(Box::new(FlowRouter {
+                flow: flow.clone(),
+                state: state.clone(),
+            })),
+            callback: Arc::new(move |event| {
+                let (flow, state) = (flow.clone(), state.clone());
+                Box::pin(async move { flow.handle(event, state.get::<FlowStore>()).await })
+            }),
+        })
+    }
+}
+
+/// Router matching the trigger of a flow and events of running flows.
+struct FlowRouter {
+    flow: Arc<Flow>,
+    state: Arc<StateManager>,
+}
+
+impl Router for FlowRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        // Synchronous routers are ready on the first poll.
+        self.matches_async(event).now_or_never().unwrap_or(false)
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            match self.state.try_get::<FlowStore>() {
+                Some(store) => self.flow.matches(event, store).await,
+                None => false,
+            }
+        })
+    }
+
+    fn is_async(&self) -> bool {
+        self.flow.is_async()
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.flow.trigger.commands()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::{router::ExactMatchRouter, testing::TestEvent};
+
+    use super::*;
+
+    fn chat(user_id: &str, text: &str) -> Arc<Box<dyn Event>> {
+        TestEvent::new()
+            .text(text)
+            .user(user_id)
+            .group("20001")
+            .boxed()
+    }
+
+    /// Feed the event to the entry if it matches, returns whether it matched.
+    fn feed(entry: &Entry, event: Arc<Box<dyn Event>>) -> bool {
+        if !entry.get_router().matches(&**event) {
+            return false;
+        }
+        block_on(entry.get_handler()(event)).unwrap();
+        true
+    }
+
+    fn signup(results: Arc<Mutex<Vec<HashMap<String, String>>>>) -> Flow {
+        Flow::new("signup", "signup")
+            .state(FlowState::new("start", |_, _| {
+                Box::pin(async { Ok(Transition::goto("name")) })
+            }))
+            .state(FlowState::new("name", |event, context| {
+                Box::pin(async move {
+                    context.set("name", event.plain_text().unwrap_or_default());
+                    Ok(Transition::goto("age"))
+                })
+            }))
+            .state(
+                FlowState::new("age", move |event, context| {
+                    let results = results.clone();
+                    Box::pin(async move {
+                        context.set("age", event.plain_text().unwrap_or_default());
+                        results.lock().unwrap().push(context.data());
+                        Ok(Transition::Finish)
+                    })
+                })
+                .router(ExactMatchRouter::new("18")),
+            )
+            .cancel("cancel")
+    }
+
+    #[test]
+    fn test_flow() {
+        let state = Arc::new(StateManager::new());
+        let results = Arc::new(Mutex::new(vec![]));
+        let entry = signup(results.clone()).into_entry(state.clone()).unwrap();
+        let store = state.get::<FlowStore>();
+
+        assert!(!feed(&entry, chat("10001", "alice")));
+        assert!(feed(&entry, chat("10001", "signup")));
+        assert!(!feed(&entry, chat("10002", "bob")));
+        assert!(feed(&entry, chat("10001", "alice")));
+        assert_eq!(
+            store.get("signup", "group:20001/10001").unwrap().state,
+            "age"
+        );
+        assert!(!feed(&entry, chat("10001", "seventeen")));
+        assert!(feed(&entry, chat("10001", "18")));
+        assert!(store.get("signup", "group:20001/10001").is_none());
+        let results = results.lock().unwrap();
+        assert_eq!(results[0]["name"], "alice");
+        assert_eq!(results[0]["age"], "18");
+    }
+
+    #[test]
+    fn test_flow_cancel_and_timeout() {
+        let state = Arc::new(StateManager::new());
+        let results = Arc::new(Mutex::new(vec![]));
+        let entry = signup(results.clone()).into_entry(state.clone()).unwrap();
+        let store = state.get::<FlowStore>();
+
+        assert!(!feed(&entry, chat("10001", "cancel")));
+        feed(&entry, chat("10001", "signup"));
+        assert!(feed(&entry, chat("10001", "cancel")));
+        assert!(store.get("signup", "group:20001/10001").is_none());
+
+        // Flows with the same name would share progress.
+        assert!(signup(results.clone()).into_entry(state.clone()).is_err());
+
+        let state = Arc::new(StateManager::new());
+        let entry = signup(results.clone())
+            .timeout(Duration::ZERO)
+            .into_entry(state.clone())
+            .unwrap();
+        feed(&entry, chat("10001", "signup"));
+        assert!(!feed(&entry, chat("10001", "alice")));
+        assert!(results.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_flow_private_chat() {
+        let state = Arc::new(StateManager::new());
+        let results = Arc::new(Mutex::new(vec![]));
+        let entry = signup(results.clone())
+            .scope(FlowScope::Channel)
+            .into_entry(state.clone())
+            .unwrap();
+        let store = state.get::<FlowStore>();
+        let private = |user_id, text| TestEvent::new().text(text).user(user_id).boxed();
+
+        assert!(feed(&entry, private("10001", "signup")));
+        assert_eq!(store.get("signup", "private:10001").unwrap().state, "name");
+        // Private chats of other users are other channels.
+        assert!(!feed(&entry, private("10002", "alice")));
+        assert!(feed(&entry, private("10001", "alice")));
+        assert!(feed(&entry, private("10001", "18")));
+        assert_eq!(results.lock().unwrap()[0]["name"], "alice");
+        assert_eq!(
+            FlowScope::User.key(&**private("10001", "")).unwrap(),
+            "private:10001/10001"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_steps_serialized() {
+        let state = Arc::new(StateManager::new());
+        let names = Arc::new(Mutex::new(vec![]));
+        let recorded = names.clone();
+        let entry = Flow::new("slow", "start")
+            .state(FlowState::new("start", |_, _| {
+                Box::pin(async { Ok(Transition::goto("name")) })
+            }))
+            .state(FlowState::new("name", move |event, _| {
+                let recorded = recorded.clone();
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    let name = event.plain_text().unwrap_or_default().to_string();
+                    recorded.lock().unwrap().push(name);
+                    Ok(Transition::goto("done"))
+                })
+            }))
+            .state(
+                FlowState::new("done", |_, _| Box::pin(async { Ok(Transition::Finish) }))
+                    .router("ok"),
+            )
+            .into_entry(state.clone())
+            .unwrap();
+
+        feed(&entry, chat("10001", "start"));
+        // Both events match the "name" state, the second one runs after the
+        // first step moved the flow to "done".
+        let handler = entry.get_handler();
+        let (first, second) = tokio::join!(
+            handler(chat("10001", "alice")),
+            handler(chat("10001", "bob"))
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(*names.lock().unwrap(), ["alice"]);
+        let progress = state.get::<FlowStore>().get("slow", "group:20001/10001");
+        assert_eq!(progress.unwrap().state, "done");
+    }
+}
@@ -2,45 +2,492 @@ use std::sync::Arc;
 
 use anyhow::Result;
 
-use crate::{entry::Entry, event::Event, queue::EventQueue};
+use crate::{
+    command::UnknownCommand,
+    entry::Entry,
+    event::Event,
+    index::RouterIndex,
+    queue::EventQueue,
+    router::{Cost, MatchContext},
+    session::Sessions,
+};
 
 #[derive(Default, Clone)]
 pub struct Handler {
-    pub entries: Vec<Entry>,
+    /// Registered entries, the index refers to them by position.
+    entries: Vec<Entry>,
+    index: RouterIndex,
+    suggest: bool,
+    sessions: Arc<Sessions>,
+    context: Arc<MatchContext>,
 }
 
 impl Handler {
     pub fn new(entries: Vec<Entry>) -> Self {
-        Self { entries }
+        let mut handler = Self {
+            entries,
+            ..Default::default()
+        };
+        handler.reindex();
+        handler
     }
 
     pub fn empty() -> Self {
-        Self { entries: vec![] }
+        Self::default()
+    }
+
+    /// Get the registered entries.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
     }
 
     pub fn extend<E: IntoIterator<Item = Entry>>(&mut self, entries: E) {
         self.entries.extend(entries);
+        self.reindex();
+    }
+
+    /// Remove the entries with the ID, returns whether any was registered.
+    pub fn remove(&mut self, id: &str) -> bool {
+        let len = self.entries.len();
+        self.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    /// Keep only the entries the predicate returns `true` for.
+    pub fn retain<F: FnMut(&Entry) -> bool>(&mut self, f: F) {
+        self.entries.retain(f);
+        self.reindex();
+    }
+
+    /// Rebuild the router index from the entries.
+    fn reindex(&mut self) {
+        let entries = &self.entries;
+        self.index = self
+            .context
+            .clone()
+            .sync_scope(|| RouterIndex::new(entries));
+    }
+
+    /// Get the settings routers are evaluated with.
+    pub fn context(&self) -> &MatchContext {
+        &self.context
+    }
+
+    /// Modify the settings routers are evaluated with.
+    pub fn update_context<F: FnOnce(&mut MatchContext)>(&mut self, f: F) {
+        f(Arc::make_mut(&mut self.context));
+        self.reindex();
+    }
+
+    /// Reply suggestions of similar commands to unknown commands.
+    pub fn suggest_commands(&mut self, enabled: bool) {
+        self.suggest = enabled;
+    }
+
+    /// Get the sessions waiting for events.
+    pub fn sessions(&self) -> &Arc<Sessions> {
+        &self.sessions
     }
 
     pub async fn input(&self, event: Arc<Box<dyn Event>>) -> Result<()> {
-        let mut queue = self.matches(&**event);
+        self.context.clone().scope(self.dispatch(event)).await
+    }
+
+    async fn dispatch(&self, event: Arc<Box<dyn Event>>) -> Result<()> {
+        if self.sessions.deliver(&event).await {
+            return Ok(());
+        }
+        let queue = self.evaluate(event.clone()).await;
+        if !self.run(queue, &event).await? {
+            self.unknown_command(event).await?;
+        }
+        Ok(())
+    }
+
+    /// Run the callbacks of the matched entries in order, returns whether
+    /// any of them handles a command.
+    async fn run(&self, mut queue: EventQueue<Entry>, event: &Arc<Box<dyn Event>>) -> Result<bool> {
+        let mut command_matched = false;
         while let Some(entry) = queue.pop() {
-            entry.get_handler()(event.clone()).await?;
+            command_matched |= !entry.get_router().commands().is_empty();
+            let callback = entry.get_handler()(event.clone());
+            self.sessions.clone().scope(callback).await?;
+        }
+        Ok(command_matched)
+    }
+
+    /// Emit an [`UnknownCommand`] event if the event looks like a command.
+    ///
+    /// The event is only delivered to entries declaring its type, e.g. with
+    /// an [`EventTypeRouter`], so other routers do not see the message twice.
+    ///
+    /// [`EventTypeRouter`]: crate::router::EventTypeRouter
+    async fn unknown_command(&self, event: Arc<Box<dyn Event>>) -> Result<()> {
+        let commands = self.entries.iter().flat_map(|e| e.get_router().commands());
+        let Some(unknown) = UnknownCommand::parse(event, commands) else {
+            return Ok(());
+        };
+        if self.suggest {
+            if let Some(text) = unknown.suggestion_text() {
+                unknown.reply(text.into()).await?;
+            }
         }
+        let event: Arc<Box<dyn Event>> = Arc::new(Box::new(unknown));
+        let candidates = self
+            .index
+            .typed(event.event_type())
+            .iter()
+            .filter_map(|&i| self.entries.get(i))
+            .collect();
+        let queue = self.evaluate_in(candidates, event.clone()).await;
+        self.run(queue, &event).await?;
         Ok(())
     }
 
-    #[inline]
-    pub fn matches(&self, event: &dyn Event) -> EventQueue<Entry> {
+    /// Get the entries which may match the event in order.
+    fn candidates(&self, event: &dyn Event) -> Vec<&Entry> {
+        match self.index.candidates(event) {
+            Some(candidates) => candidates
+                .into_iter()
+                .filter_map(|i| self.entries.get(i))
+                .collect(),
+            None => self.entries.iter().collect(),
+        }
+    }
+
+    /// Match entries against the event, async routers are awaited and
+    /// expensive routers are evaluated on the blocking thread pool.
+    pub async fn matches(&self, event: Arc<Box<dyn Event>>) -> EventQueue<Entry> {
+        self.context.clone().scope(self.evaluate(event)).await
+    }
+
+    async fn evaluate(&self, event: Arc<Box<dyn Event>>) -> EventQueue<Entry> {
+        self.evaluate_in(self.candidates(&**event), event).await
+    }
+
+    async fn evaluate_in(
+        &self,
+        candidates: Vec<&Entry>,
+        event: Arc<Box<dyn Event>>,
+    ) -> EventQueue<Entry> {
         let mut queue = EventQueue::new();
-        for entry in self.entries.iter() {
-            if entry.get_router().matches(event) {
+        let mut tasks = vec![];
+        let mut pending = vec![];
+        for entry in candidates {
+            if entry.get_router().is_async() {
+                let event = &**event;
+                pending.push(async move {
+                    entry
+                        .get_router()
+                        .matches_async(event)
+                        .await
+                        .then_some(entry)
+                });
+            } else if entry.get_router().cost() == Cost::Expensive {
+                let (entry, event) = (entry.clone(), event.clone());
+                let context = self.context.clone();
+                tasks.push(tokio::task::spawn_blocking(move || {
+                    context.sync_scope(|| entry.get_router().matches(&**event).then_some(entry))
+                }));
+            } else if entry.get_router().matches(&**event) {
                 queue.push(entry.get_priority(), entry.clone());
             }
         }
+        for entry in futures::future::join_all(pending)
+            .await
+            .into_iter()
+            .flatten()
+        {
+            queue.push(entry.get_priority(), entry.clone());
+        }
+        for result in futures::future::join_all(tasks).await {
+            match result {
+                Ok(Some(entry)) => queue.push(entry.get_priority(), entry),
+                Ok(None) => {}
+                Err(e) => log::error!("Error evaluating router: {}", e),
+            }
+        }
         queue
     }
 }
 
 unsafe impl Send for Handler {}
 unsafe impl Sync for Handler {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::future::BoxFuture;
+
+    use crate::{
+        flow::{Flow, FlowState, Transition},
+        router::{
+            AnyRouter, Async, AsyncRouter, CommandRouter, ContainsRouter, Cost, EventTypeRouter,
+            GroupRouter, Router, RouterExt, StartsWithRouter,
+        },
+        runtime::StateManager,
+        testing::TestEvent,
+        types::HandlerCallback,
+    };
+
+    use super::*;
+
+    fn callback(_event: Arc<Box<dyn Event>>) -> HandlerCallback {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn entry<R: Router + 'static>(id: &'static str, router: R) -> Entry {
+        Entry {
+            id,
+            priority: 0,
+            router: Arc::new(Box::new(router)),
+            callback: Arc::new(callback),
+        }
+    }
+
+    async fn matched(handler: &Handler, text: &str) -> Vec<&'static str> {
+        let mut queue = handler.matches(Arc::new(Box::new(text.to_string()))).await;
+        let mut ids = vec![];
+        while let Some(entry) = queue.pop() {
+            ids.push(entry.id);
+        }
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_indexed_matches() {
+        let mut handler = Handler::new(vec![
+            entry("exact", "hello"),
+            entry("prefix", StartsWithRouter::new("hel")),
+            entry("command", CommandRouter::command(["help"])),
+            entry("contains", ContainsRouter::new("lo")),
+            entry("any", AnyRouter::new(vec!["hi".boxed(), "/help".boxed()])),
+            entry("and", "hello".and(ContainsRouter::new("x"))),
+        ]);
+        assert_eq!(
+            matched(&handler, "hello").await,
+            ["contains", "exact", "prefix"]
+        );
+        assert_eq!(matched(&handler, "/help").await, ["any", "command"]);
+        assert_eq!(matched(&handler, "hi").await, ["any"]);
+        assert!(matched(&handler, "bye").await.is_empty());
+
+        handler.extend([entry("late", "bye")]);
+        assert_eq!(matched(&handler, "bye").await, ["late"]);
+        // Removing shifts the positions of later entries.
+        assert!(handler.remove("exact"));
+        assert!(!handler.remove("exact"));
+        assert_eq!(matched(&handler, "bye").await, ["late"]);
+        assert_eq!(matched(&handler, "hello").await, ["contains", "prefix"]);
+        handler.retain(|entry| entry.id != "prefix");
+        assert_eq!(matched(&handler, "hello").await, ["contains"]);
+    }
+
+    /// Router asserting it is not evaluated on the given thread.
+    struct BlockingRouter(std::thread::ThreadId);
+
+    impl Router for BlockingRouter {
+        fn matches(&self, event: &dyn Event) -> bool {
+            assert_ne!(std::thread::current().id(), self.0);
+            event.plain_text() == Some("hello")
+        }
+
+        fn cost(&self) -> Cost {
+            Cost::Expensive
+        }
+    }
+
+    struct AsyncHello;
+
+    impl AsyncRouter for AsyncHello {
+        fn matches<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                event.plain_text() == Some("hello")
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_routers() {
+        let handler = Handler::new(vec![
+            entry("cheap", "hello"),
+            entry("expensive", BlockingRouter(std::thread::current().id())),
+            entry("async", Async::new(AsyncHello)),
+            entry(
+                "combined",
+                Async::new(AsyncHello).and(ContainsRouter::new("x")),
+            ),
+        ]);
+        assert_eq!(
+            matched(&handler, "hello").await,
+            ["async", "cheap", "expensive"]
+        );
+        assert!(matched(&handler, "bye").await.is_empty());
+    }
+
+    /// Expensive router announcing its start to another task and waiting for
+    /// its reply.
+    struct WaitingRouter {
+        started: tokio::sync::mpsc::UnboundedSender<()>,
+        reply: Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl Router for WaitingRouter {
+        fn matches(&self, _event: &dyn Event) -> bool {
+            self.started.send(()).unwrap();
+            let reply = self.reply.lock().unwrap();
+            reply
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .is_ok()
+        }
+
+        fn cost(&self) -> Cost {
+            Cost::Expensive
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_expensive_router_in_async_combinator() {
+        let (started, mut on_start) = tokio::sync::mpsc::unbounded_channel();
+        let (replier, reply) = std::sync::mpsc::channel();
+        let waiting = WaitingRouter {
+            started,
+            reply: Mutex::new(reply),
+        };
+        let handler = Handler::new(vec![entry("combined", Async::new(AsyncHello).or(waiting))]);
+        // Only replies if the expensive router does not hold the single worker.
+        tokio::spawn(async move {
+            on_start.recv().await.unwrap();
+            replier.send(()).unwrap();
+        });
+        // Evaluate on the worker rather than on the thread blocking on the test.
+        let ids = tokio::spawn(async move { matched(&handler, "bye").await });
+        assert_eq!(ids.await.unwrap(), ["combined"]);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_command() {
+        let unknown = Arc::new(Mutex::new(vec![]));
+        let recorded = unknown.clone();
+        let mut handler = Handler::new(vec![
+            entry("help", CommandRouter::command(["help"])),
+            Entry {
+                id: "unknown",
+                priority: 0,
+                router: Arc::new(Box::new(EventTypeRouter::new(["unknown_command"]))),
+                callback: Arc::new(move |event| {
+                    let unknown = event.downcast_ref::<UnknownCommand>().unwrap();
+                    recorded.lock().unwrap().push(unknown.suggestions.clone());
+                    Box::pin(async { Ok(()) })
+                }),
+            },
+        ]);
+        handler
+            .input(Arc::new(Box::new("/help".to_string())))
+            .await
+            .unwrap();
+        handler
+            .input(Arc::new(Box::new("help".to_string())))
+            .await
+            .unwrap();
+        handler
+            .input(Arc::new(Box::new("/hlep".to_string())))
+            .await
+            .unwrap();
+        handler
+            .input(Arc::new(Box::new("/xyz".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(*unknown.lock().unwrap(), [vec!["help"], vec![]]);
+
+        // Events without reply support fail to reply the suggestions.
+        handler.suggest_commands(true);
+        assert!(handler
+            .input(Arc::new(Box::new("/hlep".to_string())))
+            .await
+            .is_err());
+        assert!(handler
+            .input(Arc::new(Box::new("/xyz".to_string())))
+            .await
+            .is_ok());
+    }
+
+    /// Entry recording the texts and event types it is called with.
+    fn recorder<R: Router + 'static>(
+        id: &'static str,
+        router: R,
+        calls: &Arc<Mutex<Vec<String>>>,
+    ) -> Entry {
+        let calls = calls.clone();
+        Entry {
+            id,
+            priority: 0,
+            router: Arc::new(Box::new(router)),
+            callback: Arc::new(move |event| {
+                calls.lock().unwrap().push(format!(
+                    "{}:{}:{}",
+                    id,
+                    event.event_type(),
+                    event.plain_text().unwrap_or_default()
+                ));
+                Box::pin(async { Ok(()) })
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_command_delivery() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let recorded = calls.clone();
+        let step = move |name: &'static str, next: Transition| {
+            let recorded = recorded.clone();
+            FlowState::new(name, move |event, _| {
+                let next = next.clone();
+                recorded.lock().unwrap().push(format!(
+                    "{}:{}:{}",
+                    name,
+                    event.event_type(),
+                    event.plain_text().unwrap_or_default()
+                ));
+                Box::pin(async move { Ok(next) })
+            })
+        };
+        let flow = Flow::new("quiz", "start")
+            .state(step("a", Transition::goto("b")))
+            .state(step("b", Transition::goto("c")))
+            .state(step("c", Transition::Finish))
+            .into_entry(Arc::new(StateManager::new()))
+            .unwrap();
+        let handler = Handler::new(vec![
+            flow,
+            entry("help", CommandRouter::command(["help"])),
+            recorder("group", GroupRouter, &calls),
+            recorder("unknown", EventTypeRouter::new(["unknown_command"]), &calls),
+        ]);
+        let chat = |text: &str| {
+            TestEvent::new()
+                .text(text)
+                .user("10001")
+                .group("20001")
+                .boxed()
+        };
+
+        handler.input(chat("start")).await.unwrap();
+        calls.lock().unwrap().clear();
+        handler.input(chat("/nope")).await.unwrap();
+        let mut calls = calls.lock().unwrap().clone();
+        calls.sort();
+        // Only the entry declaring the unknown command type sees it.
+        assert_eq!(
+            calls,
+            [
+                "b:message:/nope",
+                "group:message:/nope",
+                "unknown:unknown_command:"
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+
+/// Role of a user in the channel, ordered by privilege.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    #[default]
+    Member,
+    Admin,
+    Owner,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct User {
+    pub id: String,
+    /// Account nickname of the user.
+    pub name: Option<String>,
+    /// Nickname of the user in the channel, e.g. group card.
+    pub alias: Option<String>,
+    /// Special title granted in the channel.
+    pub title: Option<String>,
+    pub role: Role,
+}
+
+impl User {
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        Self {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Get the name to display for the user, prefers the channel alias.
+    pub fn display_name(&self) -> &str {
+        self.alias
+            .as_deref()
+            .filter(|alias| !alias.is_empty())
+            .or(self.name.as_deref())
+            .unwrap_or(&self.id)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role >= Role::Admin
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Private {
+        user_id: String,
+    },
+    Group {
+        group_id: String,
+    },
+    Guild {
+        guild_id: String,
+        channel_id: String,
+    },
+}
+
+impl Channel {
+    /// Get the ID of the channel, for private channels this is the user ID.
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Private { user_id } => user_id,
+            Self::Group { group_id } => group_id,
+            Self::Guild { channel_id, .. } => channel_id,
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        matches!(self, Self::Private { .. })
+    }
+}
+
+/// Formats the channel as a key unique across channel kinds.
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Private { user_id } => write!(f, "private:{}", user_id),
+            Self::Group { group_id } => write!(f, "group:{}", group_id),
+            Self::Guild {
+                guild_id,
+                channel_id,
+            } => write!(f, "guild:{}:{}", guild_id, channel_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_order() {
+        assert!(Role::Member < Role::Admin && Role::Admin < Role::Owner);
+        assert!(!User::new("1").is_admin());
+        for role in [Role::Admin, Role::Owner] {
+            assert!(User {
+                role,
+                ..User::new("1")
+            }
+            .is_admin());
+        }
+    }
+
+    #[test]
+    fn test_display_name() {
+        let mut user = User::new("10001");
+        assert_eq!(user.display_name(), "10001");
+        user.name = Some("alice".into());
+        assert_eq!(user.display_name(), "alice");
+        user.alias = Some(String::new());
+        assert_eq!(user.display_name(), "alice");
+        user.alias = Some("Alice".into());
+        assert_eq!(user.display_name(), "Alice");
+    }
+
+    #[test]
+    fn test_channel_id() {
+        let private = Channel::Private {
+            user_id: "10001".into(),
+        };
+        assert_eq!(private.id(), "10001");
+        assert!(private.is_private());
+        let group = Channel::Group {
+            group_id: "20001".into(),
+        };
+        assert_eq!(group.id(), "20001");
+        assert!(!group.is_private());
+        let guild = Channel::Guild {
+            guild_id: "30001".into(),
+            channel_id: "40001".into(),
+        };
+        assert_eq!(guild.id(), "40001");
+        assert!(!guild.is_private());
+        assert_eq!(private.to_string(), "private:10001");
+        assert_eq!(group.to_string(), "group:20001");
+        assert_eq!(guild.to_string(), "guild:30001:40001");
+    }
+}
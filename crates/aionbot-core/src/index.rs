@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use crate::{
+    entry::Entry,
+    event::Event,
+    router::{Descriptor, MatchContext, MatchOptions},
+};
+
+/// Trie of text prefixes, each node holds the entries of its prefix.
+#[derive(Clone, Default)]
+struct PrefixTrie {
+    entries: Vec<usize>,
+    children: HashMap<char, PrefixTrie>,
+}
+
+impl PrefixTrie {
+    fn insert(&mut self, prefix: &str, entry: usize) {
+        let mut node = self;
+        for c in prefix.chars() {
+            node = node.children.entry(c).or_default();
+        }
+        node.entries.push(entry);
+    }
+
+    /// Collect the entries of every prefix of the text.
+    fn collect(&self, text: &str, candidates: &mut Vec<usize>) {
+        let mut node = self;
+        candidates.extend(&node.entries);
+        for c in text.chars() {
+            match node.children.get(&c) {
+                Some(child) => node = child,
+                None => break,
+            }
+            candidates.extend(&node.entries);
+        }
+    }
+}
+
+/// Entries described with the same match options.
+#[derive(Clone, Default)]
+struct TextIndex {
+    exact: HashMap<String, Vec<usize>>,
+    prefixes: PrefixTrie,
+}
+
+/// Index of handler entries built from router descriptors.
+#[derive(Clone, Default)]
+pub(crate) struct RouterIndex {
+    texts: HashMap<MatchOptions, TextIndex>,
+    /// Entries which must be evaluated for every event.
+    opaque: Vec<usize>,
+    /// Entries matching only events of the type.
+    types: HashMap<String, Vec<usize>>,
+}
+
+impl RouterIndex {
+    pub fn new(entries: &[Entry]) -> Self {
+        let default = MatchContext::current().options;
+        let mut index = Self::default();
+        for (i, entry) in entries.iter().enumerate() {
+            match entry.get_router().descriptor() {
+                Descriptor::Text {
+                    exact,
+                    prefixes,
+                    options,
+                } => {
+                    let options = options.unwrap_or(default);
+                    let text = index.texts.entry(options).or_default();
+                    for key in exact {
+                        let key = options.normalize(&key).into_owned();
+                        text.exact.entry(key).or_default().push(i);
+                    }
+                    for prefix in prefixes {
+                        text.prefixes.insert(&options.normalize(&prefix), i);
+                    }
+                }
+                Descriptor::EventTypes(types) => {
+                    for event_type in types {
+                        index.types.entry(event_type).or_default().push(i);
+                    }
+                }
+                Descriptor::Opaque => index.opaque.push(i),
+            }
+        }
+        index
+    }
+
+    /// Get the indices of entries which may match the event in order, or
+    /// `None` if the index does not apply and every entry must be evaluated.
+    ///
+    /// The index does not apply in channels overriding the command prefixes,
+    /// as the overrides may change after the index is built.
+    pub fn candidates(&self, event: &dyn Event) -> Option<Vec<usize>> {
+        let text = event.plain_text()?;
+        if let Some(channel_id) = event.channel_id() {
+            if MatchContext::current()
+                .prefixes
+                .channels
+                .contains(channel_id)
+            {
+                return None;
+            }
+        }
+        let mut candidates = self.opaque.clone();
+        candidates.extend(self.typed(event.event_type()));
+        for (options, index) in &self.texts {
+            let text = options.normalize(text);
+            if let Some(entries) = index.exact.get(text.as_ref()) {
+                candidates.extend(entries);
+            }
+            index.prefixes.collect(&text, &mut candidates);
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        Some(candidates)
+    }
+
+    /// Get the indices of entries declaring they match events of the type.
+    pub fn typed(&self, event_type: &str) -> &[usize] {
+        self.types.get(event_type).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_trie() {
+        let mut trie = PrefixTrie::default();
+        trie.insert("/help", 0);
+        trie.insert("/he", 1);
+        trie.insert("/hi", 2);
+        trie.insert("", 3);
+        let mut candidates = vec![];
+        trie.collect("/help me", &mut candidates);
+        assert_eq!(candidates, vec![3, 1, 0]);
+        candidates.clear();
+        trie.collect("/h", &mut candidates);
+        assert_eq!(candidates, vec![3]);
+    }
+}
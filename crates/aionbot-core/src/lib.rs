@@ -1,14 +1,56 @@
+pub mod command;
+pub mod connection;
 pub mod entry;
 pub mod event;
+pub mod flow;
 pub mod handler;
+pub mod identity;
+mod index;
+pub mod limiter;
+pub mod message;
 pub mod plugin;
 pub mod prelude;
 pub mod queue;
 pub mod router {
+    use futures::future::BoxFuture;
+
     use crate::event::Event;
 
     pub trait Router: Send + Sync {
         fn matches(&self, event: &dyn Event) -> bool;
+
+        /// Evaluate the router asynchronously, defaults to [`Router::matches`]
+        /// which is run in place of a blocking thread if the router is
+        /// expensive.
+        fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+            Box::pin(async move {
+                if self.cost() == Cost::Expensive {
+                    cost::block_in_place(|| self.matches(event))
+                } else {
+                    self.matches(event)
+                }
+            })
+        }
+
+        /// Whether the router should be evaluated with [`Router::matches_async`].
+        fn is_async(&self) -> bool {
+            false
+        }
+
+        /// Describe which events the router may match, defaults to any event.
+        fn descriptor(&self) -> Descriptor {
+            Descriptor::Opaque
+        }
+
+        /// Hint how expensive the router is to evaluate, defaults to cheap.
+        fn cost(&self) -> Cost {
+            Cost::Cheap
+        }
+
+        /// Get the commands matched by the router with their prefixes.
+        fn commands(&self) -> Vec<CommandSet<'_>> {
+            vec![]
+        }
     }
 
     impl<T> Router for T
@@ -16,23 +58,58 @@ pub mod router {
         T: Send + Sync + AsRef<str> + 'static,
     {
         fn matches(&self, event: &dyn Event) -> bool {
-            if let Ok(val) = event.content().downcast::<&str>() {
-                *val == self.as_ref()
+            let Some(text) = event.plain_text() else {
+                return false;
+            };
+            let options = MatchContext::current().options;
+            if options.is_raw() {
+                text == self.as_ref()
             } else {
-                false
+                options.normalize(text) == options.normalize(self.as_ref())
             }
         }
+
+        fn descriptor(&self) -> Descriptor {
+            Descriptor::exact([self.as_ref()])
+        }
     }
 
+    mod asynchronous;
     mod command;
+    mod connection;
+    mod context;
+    mod cost;
+    mod descriptor;
     mod error;
+    mod event;
     mod logic;
     mod matcher;
+    mod mention;
+    mod options;
+    mod prefix;
 
-    pub use command::CommandRouter;
+    pub use asynchronous::{Async, AsyncRouter};
+    pub use command::{CommandRouter, CommandSet};
+    pub use connection::ConnectionRouter;
+    pub use context::MatchContext;
+    pub use cost::Cost;
+    pub use descriptor::Descriptor;
     pub use error::ErrorRouter;
-    pub use logic::{AllRouter, AnyRouter};
-    pub use matcher::{ContainsRouter, EndsWithRouter, ExactMatchRouter, StartsWithRouter};
+    pub use event::{
+        AdapterRouter, ChannelRouter, EventNameRouter, EventTypeRouter, GroupRouter, PrivateRouter,
+        RoleRouter, SenderRouter,
+    };
+    pub use logic::{AllRouter, AndRouter, AnyRouter, NotRouter, RouterExt, XorRouter};
+    pub use matcher::{
+        ContainsAnyRouter, ContainsRouter, EndsWithAnyRouter, EndsWithRouter, ExactMatchRouter,
+        StartsWithAnyRouter, StartsWithRouter,
+    };
+    pub use mention::ToMeRouter;
+    pub use options::MatchOptions;
+    pub use prefix::{ChannelPrefixes, CommandPrefixes};
 }
 pub mod runtime;
+pub mod session;
+#[cfg(test)]
+mod testing;
 pub mod types;
@@ -0,0 +1,375 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    event::Event,
+    message::Message,
+    types::{Callback, HandlerCallback},
+};
+
+/// Who shares the tokens of a rate limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LimitScope {
+    /// Each user has their own tokens.
+    #[default]
+    User,
+    /// All users in a channel share the tokens.
+    Channel,
+    /// Everyone shares the tokens.
+    Global,
+}
+
+/// Token bucket holding `capacity` tokens, refilled evenly over `period`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+    pub scope: LimitScope,
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            period,
+            scope: LimitScope::default(),
+        }
+    }
+
+    /// Allow one call per user every `duration`.
+    pub fn cooldown(duration: Duration) -> Self {
+        Self::new(1, duration)
+    }
+
+    pub fn scope(mut self, scope: LimitScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    pub fn per_user(self) -> Self {
+        self.scope(LimitScope::User)
+    }
+
+    pub fn per_channel(self) -> Self {
+        self.scope(LimitScope::Channel)
+    }
+
+    pub fn global(self) -> Self {
+        self.scope(LimitScope::Global)
+    }
+
+    /// Get the bucket key of the event, private chats are channels of their
+    /// own. Returns `None` if the event has no user or channel of the scope,
+    /// such events are not limited.
+    pub fn key(&self, event: &dyn Event) -> Option<String> {
+        let id = match self.scope {
+            LimitScope::User => event.emitter_id()?.to_string(),
+            LimitScope::Channel => event.channel()?.to_string(),
+            LimitScope::Global => String::new(),
+        };
+        Some(format!("{:?}:{}", self.scope, id))
+    }
+
+    fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.refill_rate()).min(limit.capacity as f64);
+        self.updated = now;
+    }
+
+    fn retry_after(&self, limit: &RateLimit) -> Duration {
+        Duration::from_secs_f64(((1.0 - self.tokens) / limit.refill_rate()).max(0.0))
+    }
+
+    fn is_full(&self, limit: &RateLimit) -> bool {
+        self.tokens >= limit.capacity as f64
+    }
+}
+
+/// Minimum number of buckets before full buckets are evicted.
+const SWEEP_THRESHOLD: usize = 64;
+
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<(String, RateLimit), Bucket>,
+    /// Number of buckets at which full buckets are evicted next.
+    sweep_at: usize,
+}
+
+impl Buckets {
+    /// Evict buckets refilled to capacity, they are the same as new buckets.
+    fn sweep(&mut self, now: Instant) {
+        if self.buckets.len() < self.sweep_at {
+            return;
+        }
+        self.buckets.retain(|(_, limit), bucket| {
+            bucket.refill(limit, now);
+            !bucket.is_full(limit)
+        });
+        self.sweep_at = (self.buckets.len() * 2).max(SWEEP_THRESHOLD);
+    }
+}
+
+/// Token bucket rate limiter, shareable between handlers and plugins.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a token of every limit for its key, returns how long to wait if
+    /// any bucket is empty, in which case no token is taken.
+    ///
+    /// Repeated pairs of key and limit take a single token.
+    pub fn acquire<'a, I>(&self, limits: I) -> Result<(), Duration>
+    where
+        I: IntoIterator<Item = (&'a str, &'a RateLimit)>,
+    {
+        self.acquire_at(limits, Instant::now())
+    }
+
+    fn acquire_at<'a, I>(&self, limits: I, now: Instant) -> Result<(), Duration>
+    where
+        I: IntoIterator<Item = (&'a str, &'a RateLimit)>,
+    {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.sweep(now);
+        let mut acquired = HashSet::new();
+        let mut retry_after = Duration::ZERO;
+        for (key, limit) in limits {
+            let id = (key.to_string(), *limit);
+            if acquired.contains(&id) {
+                continue;
+            }
+            let bucket = buckets.buckets.entry(id.clone()).or_insert(Bucket {
+                tokens: limit.capacity as f64,
+                updated: now,
+            });
+            bucket.refill(limit, now);
+            if bucket.tokens < 1.0 {
+                retry_after = retry_after.max(bucket.retry_after(limit));
+            }
+            acquired.insert(id);
+        }
+        if !retry_after.is_zero() {
+            return Err(retry_after);
+        }
+        for id in acquired {
+            if let Some(bucket) = buckets.buckets.get_mut(&id) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Take a token of every limit for the event, skipping limits the event
+    /// has no key for.
+    pub fn check(&self, event: &dyn Event, limits: &[RateLimit]) -> Result<(), Duration> {
+        let keys = limits
+            .iter()
+            .filter_map(|limit| Some((limit.key(event)?, limit)))
+            .collect::<Vec<_>>();
+        self.acquire(keys.iter().map(|(key, limit)| (key.as_str(), *limit)))
+    }
+
+    /// Forget the buckets of the key.
+    pub fn reset(&self, key: &str) {
+        self.buckets
+            .lock()
+            .unwrap()
+            .buckets
+            .retain(|(k, _), _| k != key);
+    }
+}
+
+/// Rate limits wrapped around a handler callback.
+#[derive(Default)]
+pub struct Limited {
+    limits: Vec<RateLimit>,
+    message: Option<String>,
+    limiter: RateLimiter,
+    /// Keys notified of being limited, until the end of their wait.
+    notified: Mutex<HashMap<String, Instant>>,
+}
+
+impl Limited {
+    pub fn new(limit: RateLimit) -> Self {
+        Self::default().limit(limit)
+    }
+
+    pub fn limit(mut self, limit: RateLimit) -> Self {
+        self.limits.push(limit);
+        self
+    }
+
+    /// Reply the message when limited, `{secs}` is replaced by the seconds to
+    /// wait. The message is replied once per wait of the same keys, limited
+    /// events are dropped silently otherwise or without a message.
+    pub fn message<S: Into<String>>(mut self, message: S) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn wrap<F>(self, callback: F) -> Arc<Callback>
+    where
+        F: Fn(Arc<Box<dyn Event>>) -> HandlerCallback + Send + Sync + 'static,
+    {
+        Arc::new(
+            move |event| match self.limiter.check(&**event, &self.limits) {
+                Ok(()) => callback(event),
+                Err(retry_after) => {
+                    let message = self
+                        .message
+                        .as_ref()
+                        .filter(|_| self.notify(&**event, retry_after))
+                        .map(|message| {
+                            let secs = retry_after.as_secs_f64().ceil() as u64;
+                            message.replace("{secs}", &secs.to_string())
+                        });
+                    Box::pin(async move {
+                        match message {
+                            Some(message) => event.reply(Message::from(message)).await,
+                            None => Ok(()),
+                        }
+                    })
+                }
+            },
+        )
+    }
+
+    /// Check whether the limited event should be notified, at most once
+    /// until the wait of its keys ends.
+    fn notify(&self, event: &dyn Event, retry_after: Duration) -> bool {
+        let key = self
+            .limits
+            .iter()
+            .filter_map(|limit| limit.key(event))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let now = Instant::now();
+        let mut notified = self.notified.lock().unwrap();
+        notified.retain(|_, until| *until > now);
+        if notified.contains_key(&key) {
+            return false;
+        }
+        notified.insert(key, now + retry_after);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::testing::TestEvent;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit::new(2, Duration::from_secs(60));
+        assert!(limiter.acquire([("a", &limit)]).is_ok());
+        assert!(limiter.acquire([("a", &limit)]).is_ok());
+        let retry_after = limiter.acquire([("a", &limit)]).unwrap_err();
+        assert!(retry_after > Duration::from_secs(29) && retry_after <= Duration::from_secs(30));
+        assert!(limiter.acquire([("b", &limit)]).is_ok());
+
+        // No token is taken when any bucket is empty.
+        let global = RateLimit::new(1, Duration::from_secs(60)).global();
+        assert!(limiter.acquire([("a", &limit), ("", &global)]).is_err());
+        assert!(limiter.acquire([("", &global)]).is_ok());
+
+        limiter.reset("a");
+        assert!(limiter.acquire([("a", &limit)]).is_ok());
+
+        let fast = RateLimit::cooldown(Duration::ZERO);
+        assert!(limiter.acquire([("c", &fast)]).is_ok());
+        assert!(limiter.acquire([("c", &fast)]).is_ok());
+    }
+
+    #[test]
+    fn test_limited_callback() {
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let callback = Limited::new(RateLimit::cooldown(Duration::from_secs(60)))
+            .message("wait {secs}s")
+            .wrap(move |_| {
+                *counter.lock().unwrap() += 1;
+                Box::pin(async { Ok(()) })
+            });
+        let event = TestEvent::new().text("hello").user("10001").boxed();
+        block_on(callback(event.clone())).unwrap();
+        // Replying is unsupported by plain text events.
+        assert!(block_on(callback(event.clone())).is_err());
+        // Notified once per wait.
+        block_on(callback(event)).unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_limit_keys() {
+        let limiter = RateLimiter::new();
+        let limits = [RateLimit::cooldown(Duration::from_secs(60)).per_channel()];
+        let first = TestEvent::new().user("1");
+        let second = TestEvent::new().user("2");
+        // Private chats are separate channels.
+        assert!(limiter.check(&first, &limits).is_ok());
+        assert!(limiter.check(&second, &limits).is_ok());
+        assert!(limiter.check(&first, &limits).is_err());
+        assert!(limiter
+            .check(&TestEvent::new().user("1").group("1"), &limits)
+            .is_ok());
+
+        // Events without a user or channel are not limited.
+        let anonymous = "hello".to_string();
+        assert_eq!(limits[0].key(&anonymous), None);
+        assert!(limiter.check(&anonymous, &limits).is_ok());
+        assert!(limiter.check(&anonymous, &limits).is_ok());
+    }
+
+    #[test]
+    fn test_repeated_limits() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit::new(2, Duration::from_secs(60));
+        assert!(limiter.acquire([("a", &limit), ("a", &limit)]).is_ok());
+        assert!(limiter.acquire([("a", &limit)]).is_ok());
+        assert!(limiter.acquire([("a", &limit)]).is_err());
+    }
+
+    #[test]
+    fn test_evict_full_buckets() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit::cooldown(Duration::from_secs(60));
+        let now = Instant::now();
+        for i in 1..SWEEP_THRESHOLD {
+            assert!(limiter.acquire_at([(&*i.to_string(), &limit)], now).is_ok());
+        }
+        let later = now + Duration::from_secs(60);
+        assert!(limiter.acquire_at([("a", &limit)], later).is_ok());
+        let len = || limiter.buckets.lock().unwrap().buckets.len();
+        assert_eq!(len(), SWEEP_THRESHOLD);
+        // Refilled buckets are evicted without losing the state of others.
+        assert!(limiter.acquire_at([("b", &limit)], later).is_ok());
+        assert_eq!(len(), 2);
+        assert!(limiter.acquire_at([("a", &limit)], later).is_err());
+    }
+}
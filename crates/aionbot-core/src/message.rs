@@ -0,0 +1,169 @@
+use std::{fmt, path::PathBuf};
+
+/// Source of an image segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Image {
+    Path(PathBuf),
+    Url(String),
+    Bytes(Vec<u8>),
+}
+
+/// Platform-neutral message segment, adapters convert it to their own
+/// wire format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    /// Mention a user by ID.
+    Mention(String),
+    Image(Image),
+    /// Reply to a message by ID.
+    Reply(String),
+    /// Platform emoji or face by ID.
+    Emoji(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    segments: Vec<Segment>,
+}
+
+impl Message {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, segment: Segment) {
+        if let (Some(Segment::Text(last)), Segment::Text(text)) =
+            (self.segments.last_mut(), &segment)
+        {
+            last.push_str(text);
+            return;
+        }
+        self.segments.push(segment);
+    }
+
+    pub fn segment(mut self, segment: Segment) -> Self {
+        self.push(segment);
+        self
+    }
+
+    pub fn text<S: Into<String>>(self, text: S) -> Self {
+        self.segment(Segment::Text(text.into()))
+    }
+
+    pub fn mention<S: Into<String>>(self, user_id: S) -> Self {
+        self.segment(Segment::Mention(user_id.into()))
+    }
+
+    pub fn image(self, image: Image) -> Self {
+        self.segment(Segment::Image(image))
+    }
+
+    pub fn image_path<P: Into<PathBuf>>(self, path: P) -> Self {
+        self.image(Image::Path(path.into()))
+    }
+
+    pub fn image_url<S: Into<String>>(self, url: S) -> Self {
+        self.image(Image::Url(url.into()))
+    }
+
+    pub fn image_bytes<B: Into<Vec<u8>>>(self, bytes: B) -> Self {
+        self.image(Image::Bytes(bytes.into()))
+    }
+
+    pub fn reply_to<S: Into<String>>(self, message_id: S) -> Self {
+        self.segment(Segment::Reply(message_id.into()))
+    }
+
+    pub fn emoji<S: Into<String>>(self, id: S) -> Self {
+        self.segment(Segment::Emoji(id.into()))
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Get the concatenated text segments of the message.
+    pub fn plain_text(&self) -> String {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.plain_text())
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::new().text(text)
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::new().text(text)
+    }
+}
+
+impl From<Segment> for Message {
+    fn from(segment: Segment) -> Self {
+        Self::new().segment(segment)
+    }
+}
+
+impl FromIterator<Segment> for Message {
+    fn from_iter<I: IntoIterator<Item = Segment>>(iter: I) -> Self {
+        let mut message = Self::new();
+        iter.into_iter().for_each(|segment| message.push(segment));
+        message
+    }
+}
+
+impl IntoIterator for Message {
+    type Item = Segment;
+    type IntoIter = std::vec::IntoIter<Segment>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_builder() {
+        let message = Message::new()
+            .reply_to("1")
+            .mention("10001")
+            .text(" hello")
+            .text(", world")
+            .image_url("https://example.com/a.png")
+            .emoji("14");
+        assert_eq!(
+            message.segments(),
+            &[
+                Segment::Reply("1".into()),
+                Segment::Mention("10001".into()),
+                Segment::Text(" hello, world".into()),
+                Segment::Image(Image::Url("https://example.com/a.png".into())),
+                Segment::Emoji("14".into()),
+            ]
+        );
+        assert_eq!(message.plain_text(), " hello, world");
+        assert_eq!(Message::from("hi").to_string(), "hi");
+    }
+}
@@ -1,4 +1,11 @@
+pub use crate::command::{Command, CommandArgs, CommandGroup, UnknownCommand};
+pub use crate::connection::{ConnectionEvent, DisconnectReason};
 pub use crate::entry::Entry;
-pub use crate::event::Event;
+pub use crate::event::{Capabilities, Content, Event, EventError};
+pub use crate::flow::{Flow, FlowContext, FlowScope, FlowState, Transition};
+pub use crate::identity::{Channel, Role, User};
+pub use crate::limiter::{LimitScope, Limited, RateLimit, RateLimiter};
+pub use crate::message::Message;
 pub use crate::router::*;
+pub use crate::session::{wait_for, wait_reply, SessionError, SessionFilter};
 pub use crate::types::*;
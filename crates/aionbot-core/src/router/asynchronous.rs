@@ -0,0 +1,105 @@
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::event::Event;
+
+use super::{CommandSet, Cost, Router};
+
+/// Router which needs to await, e.g. to query a database.
+pub trait AsyncRouter: Send + Sync {
+    fn matches<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool>;
+
+    /// Get the commands matched by the router with their prefixes.
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        vec![]
+    }
+}
+
+/// Adapter to use an [`AsyncRouter`] as a [`Router`].
+///
+/// The handler and combinators await the inner router. Synchronous evaluation
+/// never blocks, it only polls the future once and does not match if the
+/// router is not ready yet.
+pub struct Async<R: AsyncRouter> {
+    pub router: R,
+}
+
+impl<R: AsyncRouter> Async<R> {
+    pub fn new(router: R) -> Self {
+        Self { router }
+    }
+}
+
+impl<R: AsyncRouter> Router for Async<R> {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.router
+            .matches(event)
+            .now_or_never()
+            .unwrap_or_else(|| {
+                log::warn!("Async router is not ready in synchronous evaluation, not matched.");
+                false
+            })
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        self.router.matches(event)
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Moderate
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.router.commands()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router::RouterExt;
+
+    use super::*;
+
+    struct AllowList(Vec<&'static str>);
+
+    impl AsyncRouter for AllowList {
+        fn matches<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+            Box::pin(async move {
+                futures::future::ready(()).await;
+                event
+                    .plain_text()
+                    .is_some_and(|text| self.0.contains(&text))
+            })
+        }
+    }
+
+    struct Pending;
+
+    impl AsyncRouter for Pending {
+        fn matches<'a>(&'a self, _event: &'a dyn Event) -> BoxFuture<'a, bool> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    #[test]
+    fn test_async_router() {
+        let event = "hello".to_string();
+        let router = Async::new(AllowList(vec!["hello"]));
+        assert!(router.is_async());
+        assert!(router.matches(&event));
+        assert!(futures::executor::block_on(router.matches_async(&event)));
+
+        let router = "hello".and(Async::new(AllowList(vec!["world"])));
+        assert!(router.is_async());
+        assert!(!futures::executor::block_on(router.matches_async(&event)));
+        let router = "world".or(Async::new(AllowList(vec!["hello"]))).not();
+        assert!(router.is_async());
+        assert!(!futures::executor::block_on(router.matches_async(&event)));
+        assert!(!"hello".or("world").is_async());
+        // Synchronous evaluation does not wait for pending routers.
+        assert!(!Async::new(Pending).matches(&event));
+    }
+}
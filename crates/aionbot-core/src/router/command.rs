@@ -1,17 +1,39 @@
 use crate::event::Event;
 
-use super::Router;
+use super::{Descriptor, MatchContext, MatchOptions, Router};
 
+/// Names of commands matched by a router with their prefixes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandSet<'a> {
+    /// Own prefixes of the router, the prefixes of the handler if unset.
+    pub prefixes: Option<&'a [String]>,
+    pub names: Vec<&'a str>,
+}
+
+/// Router matching commands such as `/help` or `/help@bot args`.
+///
+/// The command must end at a token boundary, so `/helper` does not match
+/// `help` unless abbreviation matching is enabled.
 pub struct CommandRouter {
-    pub prefixes: Vec<String>,
+    /// Prefixes of the command, uses the prefixes of the handler if unset.
+    pub prefixes: Option<Vec<String>>,
     pub command: Vec<String>,
+    /// Alternative names resolving to the first command.
+    pub aliases: Vec<String>,
+    /// Accepted `@botname` suffixes, any suffix is accepted if empty.
+    pub bot_names: Vec<String>,
+    /// Match unambiguous prefixes of the command names, e.g. `/he`.
+    pub abbreviation: bool,
 }
 
 impl Default for CommandRouter {
     fn default() -> Self {
         Self {
-            prefixes: vec!["/".into()],
+            prefixes: None,
             command: ["help".into()].to_vec(),
+            aliases: vec![],
+            bot_names: vec![],
+            abbreviation: false,
         }
     }
 }
@@ -22,8 +44,9 @@ impl CommandRouter {
         command: C,
     ) -> Self {
         Self {
-            prefixes,
+            prefixes: Some(prefixes),
             command: command.into_iter().map(Into::into).collect(),
+            ..Default::default()
         }
     }
 
@@ -33,28 +56,160 @@ impl CommandRouter {
             ..Default::default()
         }
     }
-}
 
-impl Router for CommandRouter {
-    fn matches(&self, event: &dyn Event) -> bool {
-        if let Ok(val) = event.content().downcast::<&str>() {
-            for prefix in &self.prefixes {
-                if val.starts_with(prefix) {
-                    let command = val.strip_prefix(prefix).unwrap();
-                    if self.command.iter().any(|c| command.starts_with(c)) {
-                        return true;
+    pub fn prefixes<S: Into<String>, I: IntoIterator<Item = S>>(mut self, prefixes: I) -> Self {
+        self.prefixes = Some(prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn alias<S: Into<String>>(mut self, alias: S) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    pub fn bot_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.bot_names.push(name.into());
+        self
+    }
+
+    pub fn abbreviation(mut self, abbreviation: bool) -> Self {
+        self.abbreviation = abbreviation;
+        self
+    }
+
+    /// Get the command names and aliases with the command they resolve to.
+    fn names(&self) -> impl Iterator<Item = (&str, &str)> {
+        let first = self.command.first().map(String::as_str).unwrap_or_default();
+        self.command
+            .iter()
+            .map(|c| (c.as_str(), c.as_str()))
+            .chain(self.aliases.iter().map(move |a| (a.as_str(), first)))
+    }
+
+    /// Strip the end of a command and an optional `@botname` suffix.
+    fn strip_end<'a>(&self, rest: &'a str) -> Option<&'a str> {
+        if let Some(mention) = rest.strip_prefix('@') {
+            let end = mention.find(char::is_whitespace).unwrap_or(mention.len());
+            let (name, rest) = mention.split_at(end);
+            if !self.bot_names.is_empty() && !self.bot_names.iter().any(|n| n == name) {
+                return None;
+            }
+            Some(rest.trim_start())
+        } else if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+            Some(rest.trim_start())
+        } else {
+            None
+        }
+    }
+
+    /// Parse the text as a command, returns the matched command and the rest
+    /// of the text with leading whitespace trimmed.
+    pub fn parse<'s, 'a>(&'s self, text: &'a str) -> Option<(&'s str, &'a str)> {
+        self.parse_in(text, None)
+    }
+
+    /// Parse the text as a command sent in the channel, routers without
+    /// prefixes use the prefixes of the handler in the channel.
+    pub fn parse_in<'s, 'a>(
+        &'s self,
+        text: &'a str,
+        channel_id: Option<&str>,
+    ) -> Option<(&'s str, &'a str)> {
+        match &self.prefixes {
+            Some(prefixes) => self.parse_prefixed(prefixes, text),
+            None => {
+                let context = MatchContext::current();
+                self.parse_prefixed(&context.prefixes.resolve(channel_id), text)
+            }
+        }
+    }
+
+    fn parse_prefixed<'s, 'a>(
+        &'s self,
+        prefixes: &[String],
+        text: &'a str,
+    ) -> Option<(&'s str, &'a str)> {
+        for prefix in prefixes {
+            let Some(text) = text.strip_prefix(prefix.as_str()) else {
+                continue;
+            };
+            let matched = self
+                .names()
+                .filter_map(|(name, command)| {
+                    let rest = self.strip_end(text.strip_prefix(name)?)?;
+                    Some((name.len(), command, rest))
+                })
+                .max_by_key(|(len, ..)| *len);
+            if let Some((_, command, rest)) = matched {
+                return Some((command, rest));
+            }
+            if self.abbreviation {
+                let end = text
+                    .find(|c: char| c.is_whitespace() || c == '@')
+                    .unwrap_or(text.len());
+                let (abbr, rest) = text.split_at(end);
+                if abbr.is_empty() {
+                    continue;
+                }
+                let mut commands = self
+                    .names()
+                    .filter(|(name, _)| name.starts_with(abbr))
+                    .map(|(_, command)| command)
+                    .collect::<Vec<_>>();
+                commands.sort_unstable();
+                commands.dedup();
+                if let [command] = commands[..] {
+                    if let Some(rest) = self.strip_end(rest) {
+                        return Some((command, rest));
                     }
                 }
             }
-            false
+        }
+        None
+    }
+}
+
+impl Router for CommandRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        if let Some(val) = event.plain_text() {
+            self.parse_in(val, event.channel_id()).is_some()
         } else {
             false
         }
     }
+
+    /// Describes the commands with the own or default prefixes, prefixes of
+    /// channels are not indexed as they may change.
+    fn descriptor(&self) -> Descriptor {
+        let prefixes = match &self.prefixes {
+            Some(prefixes) => prefixes.clone(),
+            None => MatchContext::current().prefixes.default.clone(),
+        };
+        let descriptor = if self.abbreviation {
+            Descriptor::prefix(prefixes)
+        } else {
+            Descriptor::prefix(prefixes.iter().flat_map(|prefix| {
+                self.names()
+                    .map(move |(name, _)| format!("{}{}", prefix, name))
+            }))
+        };
+        descriptor.options(Some(MatchOptions::new()))
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        vec![CommandSet {
+            prefixes: self.prefixes.as_deref(),
+            names: self.names().map(|(name, _)| name).collect(),
+        }]
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use crate::{router::CommandPrefixes, testing::TestEvent};
+
     use super::*;
 
     #[test]
@@ -79,4 +234,67 @@ mod tests {
         assert!(!router.matches(&"!not cmd".to_string()));
         assert!(!router.matches(&"/cmd arg1 arg2".to_string()))
     }
+
+    #[test]
+    fn test_command_boundary() {
+        let router = CommandRouter::default();
+        assert!(!router.matches(&"/helpme".to_string()));
+        assert!(!router.matches(&"/helper".to_string()));
+        assert!(router.matches(&"/help me".to_string()));
+
+        let router = CommandRouter::command(["config", "config set"]);
+        assert_eq!(router.parse("/config set x"), Some(("config set", "x")));
+        assert_eq!(router.parse("/config get x"), Some(("config", "get x")));
+    }
+
+    #[test]
+    fn test_command_aliases_and_bot_names() {
+        let router = CommandRouter::command(["help"]).alias("h").alias("?");
+        assert_eq!(router.parse("/h topic"), Some(("help", "topic")));
+        assert_eq!(router.parse("/?"), Some(("help", "")));
+        assert_eq!(router.parse("/hx"), None);
+
+        let router = CommandRouter::command(["help"]).bot_name("aion");
+        assert_eq!(router.parse("/help@aion topic"), Some(("help", "topic")));
+        assert_eq!(router.parse("/help@other topic"), None);
+        assert_eq!(router.parse("/help topic"), Some(("help", "topic")));
+    }
+
+    #[test]
+    fn test_command_abbreviation() {
+        let router = CommandRouter::command(["help", "hello", "list"]);
+        assert_eq!(router.parse("/li"), None);
+
+        let router = router.abbreviation(true);
+        assert_eq!(router.parse("/li all"), Some(("list", "all")));
+        assert_eq!(router.parse("/hel"), None);
+        assert_eq!(router.parse("/help"), Some(("help", "")));
+        assert_eq!(router.parse("/hell@bot x"), Some(("hello", "x")));
+        assert_eq!(router.parse("/"), None);
+        assert!(router.matches(&"/l".to_string()));
+    }
+
+    #[test]
+    fn test_context_prefixes() {
+        let prefixes = CommandPrefixes::new(["~"]).channel("20001", ["#"]);
+        let context = Arc::new(MatchContext::new().prefixes(prefixes));
+        let router = CommandRouter::command(["help"]);
+        context.sync_scope(|| {
+            assert!(router.matches(&"~help".to_string()));
+            assert!(!router.matches(&"/help".to_string()));
+            assert!(router.matches(&TestEvent::new().group("20002").text("~help")));
+            assert!(router.matches(&TestEvent::new().group("20001").text("#help")));
+            assert!(!router.matches(&TestEvent::new().group("20001").text("~help")));
+        });
+        assert!(!router.matches(&"~help".to_string()));
+        assert!(!router.prefixes(["!"]).matches(&"~help".to_string()));
+    }
+
+    #[test]
+    fn test_command_parse() {
+        let router = CommandRouter::command(["cmd", "command"]);
+        assert_eq!(router.parse("/cmd  arg1 arg2"), Some(("cmd", "arg1 arg2")));
+        assert_eq!(router.parse("/command"), Some(("command", "")));
+        assert_eq!(router.parse("cmd arg"), None);
+    }
 }
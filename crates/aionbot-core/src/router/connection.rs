@@ -0,0 +1,60 @@
+use crate::{connection::ConnectionEvent, event::Event};
+
+use super::Router;
+
+/// Router matching bot connection lifecycle events.
+#[derive(Default)]
+pub struct ConnectionRouter {
+    pub connected: Option<bool>,
+}
+
+impl ConnectionRouter {
+    /// Match both connected and disconnected events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connected() -> Self {
+        Self {
+            connected: Some(true),
+        }
+    }
+
+    pub fn disconnected() -> Self {
+        Self {
+            connected: Some(false),
+        }
+    }
+}
+
+impl Router for ConnectionRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        if let Some(event) = event.downcast_ref::<ConnectionEvent>() {
+            self.connected
+                .is_none_or(|connected| connected == event.is_connected())
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection::DisconnectReason;
+
+    use super::*;
+
+    #[test]
+    fn test_connection_router() {
+        let connected = ConnectionEvent::connected("test", "10001");
+        let disconnected = ConnectionEvent::disconnected("test", "10001", DisconnectReason::Closed);
+        assert!(ConnectionRouter::new().matches(&connected));
+        assert!(ConnectionRouter::new().matches(&disconnected));
+        assert!(ConnectionRouter::connected().matches(&connected));
+        assert!(!ConnectionRouter::connected().matches(&disconnected));
+        assert!(ConnectionRouter::disconnected().matches(&disconnected));
+        assert!(!ConnectionRouter::new().matches(&"10001".to_string()));
+        // The bot is not a user emitting the event.
+        assert_eq!(connected.emitter_id(), None);
+    }
+}
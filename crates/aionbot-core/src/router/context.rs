@@ -0,0 +1,57 @@
+use std::{
+    future::Future,
+    sync::{Arc, LazyLock},
+};
+
+use super::{CommandPrefixes, MatchOptions};
+
+tokio::task_local! {
+    static CONTEXT: Arc<MatchContext>;
+}
+
+static DEFAULT: LazyLock<Arc<MatchContext>> = LazyLock::new(Arc::default);
+
+/// Settings of the handler evaluating routers.
+///
+/// Routers read the context of the handler evaluating them, routers evaluated
+/// outside of a handler use the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchContext {
+    /// Prefixes of command routers without their own prefixes.
+    pub prefixes: CommandPrefixes,
+    /// Options of text routers without their own options.
+    pub options: MatchOptions,
+}
+
+impl MatchContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefixes(mut self, prefixes: CommandPrefixes) -> Self {
+        self.prefixes = prefixes;
+        self
+    }
+
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Get the context of the handler evaluating routers.
+    pub fn current() -> Arc<Self> {
+        CONTEXT
+            .try_with(Arc::clone)
+            .unwrap_or_else(|_| DEFAULT.clone())
+    }
+
+    /// Run the future with the context available to routers.
+    pub fn scope<F: Future>(self: Arc<Self>, future: F) -> impl Future<Output = F::Output> {
+        CONTEXT.scope(self, future)
+    }
+
+    /// Run the function with the context available to routers.
+    pub fn sync_scope<R, F: FnOnce() -> R>(self: Arc<Self>, f: F) -> R {
+        CONTEXT.sync_scope(self, f)
+    }
+}
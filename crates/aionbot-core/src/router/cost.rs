@@ -0,0 +1,26 @@
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Hint of how expensive a router is to evaluate, ordered from cheapest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Cost {
+    /// Simple checks such as comparing text or event fields.
+    #[default]
+    Cheap,
+    /// Checks scanning the text, e.g. substring or multi-pattern search.
+    Moderate,
+    /// Blocking checks such as complex regexes or classifiers, evaluated on
+    /// the blocking thread pool by the handler, or in place of a blocking
+    /// thread when awaited by an async router.
+    Expensive,
+}
+
+/// Run a blocking evaluation without stalling the other tasks of the worker
+/// thread, runs it inline outside of a multi-threaded runtime.
+pub(crate) fn block_in_place<R, F: FnOnce() -> R>(f: F) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
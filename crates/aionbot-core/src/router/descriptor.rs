@@ -0,0 +1,96 @@
+use super::MatchOptions;
+
+/// Hint describing which events a router may match, used by the handler to
+/// index entries instead of evaluating every router for every event.
+///
+/// A descriptor only narrows down candidates, the router is still evaluated
+/// before its entry is dispatched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Descriptor {
+    /// For events with plain text, the router matches only if the text
+    /// normalized with the options equals one of `exact` or starts with one
+    /// of `prefixes`. Unset options resolve to the options of the handler.
+    Text {
+        exact: Vec<String>,
+        prefixes: Vec<String>,
+        options: Option<MatchOptions>,
+    },
+    /// The router matches only events of the types, see [`Event::event_type`].
+    ///
+    /// Events synthesized by the handler, e.g. [`UnknownCommand`], are only
+    /// delivered to routers declaring their type.
+    ///
+    /// [`Event::event_type`]: crate::event::Event::event_type
+    /// [`UnknownCommand`]: crate::command::UnknownCommand
+    EventTypes(Vec<String>),
+    /// The router may match any event.
+    #[default]
+    Opaque,
+}
+
+impl Descriptor {
+    pub fn exact<S: Into<String>, I: IntoIterator<Item = S>>(texts: I) -> Self {
+        Self::Text {
+            exact: texts.into_iter().map(Into::into).collect(),
+            prefixes: vec![],
+            options: None,
+        }
+    }
+
+    pub fn prefix<S: Into<String>, I: IntoIterator<Item = S>>(prefixes: I) -> Self {
+        Self::Text {
+            exact: vec![],
+            prefixes: prefixes.into_iter().map(Into::into).collect(),
+            options: None,
+        }
+    }
+
+    /// Set the match options of a text descriptor.
+    pub fn options(self, options: Option<MatchOptions>) -> Self {
+        match self {
+            Self::Text {
+                exact, prefixes, ..
+            } => Self::Text {
+                exact,
+                prefixes,
+                options,
+            },
+            descriptor => descriptor,
+        }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        matches!(self, Self::Opaque)
+    }
+
+    /// Describe a router matching if either of the routers matches.
+    pub fn union(self, other: Self) -> Self {
+        match (self, other) {
+            (
+                Self::Text {
+                    mut exact,
+                    mut prefixes,
+                    options,
+                },
+                Self::Text {
+                    exact: other_exact,
+                    prefixes: other_prefixes,
+                    options: other_options,
+                },
+            ) if options == other_options => {
+                exact.extend(other_exact);
+                prefixes.extend(other_prefixes);
+                Self::Text {
+                    exact,
+                    prefixes,
+                    options,
+                }
+            }
+            (Self::EventTypes(mut types), Self::EventTypes(other_types)) => {
+                types.extend(other_types);
+                Self::EventTypes(types)
+            }
+            _ => Self::Opaque,
+        }
+    }
+}
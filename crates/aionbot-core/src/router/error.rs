@@ -24,6 +24,6 @@ impl<E: Error> Default for ErrorRouter<E> {
 
 impl<E: Error + Send + Sync + 'static> Router for ErrorRouter<E> {
     fn matches(&self, event: &dyn Event) -> bool {
-        event.content().downcast::<E>().is_ok()
+        event.content().is_some_and(|content| content.is::<E>())
     }
 }
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+
+use crate::{event::Event, identity::Role};
+
+use super::{Descriptor, Router};
+
+fn collect<S: Into<String>, I: IntoIterator<Item = S>>(items: I) -> HashSet<String> {
+    items.into_iter().map(Into::into).collect()
+}
+
+/// Router matching events by [`Event::event_type`].
+pub struct EventTypeRouter {
+    pub event_types: HashSet<String>,
+}
+
+impl EventTypeRouter {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(event_types: I) -> Self {
+        Self {
+            event_types: collect(event_types),
+        }
+    }
+}
+
+impl Router for EventTypeRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.event_types.contains(event.event_type())
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        Descriptor::EventTypes(self.event_types.iter().cloned().collect())
+    }
+}
+
+/// Router matching events by [`Event::name`].
+pub struct EventNameRouter {
+    pub names: HashSet<String>,
+}
+
+impl EventNameRouter {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(names: I) -> Self {
+        Self {
+            names: collect(names),
+        }
+    }
+}
+
+impl Router for EventNameRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.names.contains(event.name())
+    }
+}
+
+/// Router matching events emitted by the given users.
+pub struct SenderRouter {
+    pub user_ids: HashSet<String>,
+}
+
+impl SenderRouter {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(user_ids: I) -> Self {
+        Self {
+            user_ids: collect(user_ids),
+        }
+    }
+}
+
+impl Router for SenderRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        event
+            .emitter_id()
+            .is_some_and(|user_id| self.user_ids.contains(user_id))
+    }
+}
+
+/// Router matching events from the given channels.
+pub struct ChannelRouter {
+    pub channel_ids: HashSet<String>,
+}
+
+impl ChannelRouter {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(channel_ids: I) -> Self {
+        Self {
+            channel_ids: collect(channel_ids),
+        }
+    }
+}
+
+impl Router for ChannelRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        event
+            .channel_id()
+            .is_some_and(|channel_id| self.channel_ids.contains(channel_id))
+    }
+}
+
+/// Router matching events from private channels.
+#[derive(Default)]
+pub struct PrivateRouter;
+
+impl Router for PrivateRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        event.channel().is_some_and(|channel| channel.is_private())
+    }
+}
+
+/// Router matching events from group or guild channels.
+#[derive(Default)]
+pub struct GroupRouter;
+
+impl Router for GroupRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        event.channel().is_some_and(|channel| !channel.is_private())
+    }
+}
+
+/// Router matching events emitted by the given adapters.
+pub struct AdapterRouter {
+    pub adapters: HashSet<String>,
+}
+
+impl AdapterRouter {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(adapters: I) -> Self {
+        Self {
+            adapters: collect(adapters),
+        }
+    }
+}
+
+impl Router for AdapterRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.adapters.contains(event.adapter())
+    }
+}
+
+/// Router matching events whose sender has at least the given role.
+pub struct RoleRouter {
+    pub role: Role,
+}
+
+impl RoleRouter {
+    pub fn new(role: Role) -> Self {
+        Self { role }
+    }
+
+    pub fn admin() -> Self {
+        Self::new(Role::Admin)
+    }
+
+    pub fn owner() -> Self {
+        Self::new(Role::Owner)
+    }
+}
+
+impl Router for RoleRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        event
+            .sender()
+            .is_some_and(|sender| sender.role >= self.role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{identity::User, testing::TestEvent};
+
+    use super::*;
+
+    #[test]
+    fn test_event_routers() {
+        let group = TestEvent::new()
+            .sender(User {
+                role: Role::Admin,
+                ..User::new("10001")
+            })
+            .group("20001");
+        let private = TestEvent::new().user("10001");
+
+        assert!(EventTypeRouter::new(["message"]).matches(&group));
+        assert!(!EventTypeRouter::new(["notice"]).matches(&group));
+        assert!(EventNameRouter::new(["unknown_event"]).matches(&group));
+        assert!(SenderRouter::new(["10001", "10002"]).matches(&group));
+        assert!(!SenderRouter::new(["10002"]).matches(&group));
+        assert!(ChannelRouter::new(["20001"]).matches(&group));
+        assert!(!ChannelRouter::new(["10001"]).matches(&private));
+        assert!(GroupRouter.matches(&group));
+        assert!(!GroupRouter.matches(&private));
+        assert!(PrivateRouter.matches(&private));
+        assert!(!PrivateRouter.matches(&group));
+        assert!(AdapterRouter::new(["test"]).matches(&group));
+        assert!(!AdapterRouter::new(["onebot"]).matches(&group));
+        assert!(RoleRouter::admin().matches(&group));
+        assert!(!RoleRouter::owner().matches(&group));
+        assert!(!RoleRouter::admin().matches(&private));
+        assert!(!RoleRouter::admin().matches(&"hello".to_string()));
+    }
+}
@@ -1,9 +1,12 @@
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use futures::future::BoxFuture;
 
 use crate::event::Event;
 
-use super::Router;
+use super::{CommandSet, Cost, Descriptor, Router};
 
+/// Router matching every event.
 #[derive(Default)]
 pub struct AllRouter;
 
@@ -13,18 +16,397 @@ impl Router for AllRouter {
     }
 }
 
+/// Sort routers from the cheapest, keeping the order of equal costs.
+fn sort_by_cost(mut routers: Vec<Box<dyn Router>>) -> Vec<Box<dyn Router>> {
+    routers.sort_by_key(|r| r.cost());
+    routers
+}
+
+/// Get the cost of the most expensive router.
+fn max_cost(routers: &[Box<dyn Router>]) -> Cost {
+    routers.iter().map(|r| r.cost()).max().unwrap_or_default()
+}
+
+/// Router matching if any of the routers matches, evaluated from the
+/// cheapest and stops at the first match.
 pub struct AnyRouter {
     pub routers: Vec<Box<dyn Router>>,
 }
 
 impl Router for AnyRouter {
     fn matches(&self, event: &dyn Event) -> bool {
-        self.routers.par_iter().any(|r| r.matches(event))
+        self.routers.iter().any(|r| r.matches(event))
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            for router in &self.routers {
+                if router.matches_async(event).await {
+                    return true;
+                }
+            }
+            false
+        })
+    }
+
+    fn is_async(&self) -> bool {
+        self.routers.iter().any(|r| r.is_async())
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        self.routers
+            .iter()
+            .map(|r| r.descriptor())
+            .reduce(Descriptor::union)
+            .unwrap_or_else(|| Descriptor::exact::<String, _>([]))
+    }
+
+    fn cost(&self) -> Cost {
+        max_cost(&self.routers)
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.routers.iter().flat_map(|r| r.commands()).collect()
     }
 }
 
 impl AnyRouter {
     pub fn new(routers: Vec<Box<dyn Router>>) -> Self {
-        Self { routers }
+        Self {
+            routers: sort_by_cost(routers),
+        }
+    }
+}
+
+/// Router matching if all of the routers match, evaluated from the
+/// cheapest and stops at the first mismatch.
+pub struct AndRouter {
+    pub routers: Vec<Box<dyn Router>>,
+}
+
+impl Router for AndRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.routers.iter().all(|r| r.matches(event))
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            for router in &self.routers {
+                if !router.matches_async(event).await {
+                    return false;
+                }
+            }
+            true
+        })
+    }
+
+    fn is_async(&self) -> bool {
+        self.routers.iter().any(|r| r.is_async())
+    }
+
+    /// Every router must match, so any described router narrows it down.
+    fn descriptor(&self) -> Descriptor {
+        self.routers
+            .iter()
+            .map(|r| r.descriptor())
+            .find(|d| !d.is_opaque())
+            .unwrap_or_default()
+    }
+
+    fn cost(&self) -> Cost {
+        max_cost(&self.routers)
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.routers.iter().flat_map(|r| r.commands()).collect()
+    }
+}
+
+impl AndRouter {
+    pub fn new(routers: Vec<Box<dyn Router>>) -> Self {
+        Self {
+            routers: sort_by_cost(routers),
+        }
+    }
+}
+
+/// Router matching if the inner router does not match, it handles none of
+/// the commands of the inner router.
+pub struct NotRouter {
+    pub router: Box<dyn Router>,
+}
+
+impl Router for NotRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        !self.router.matches(event)
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        Box::pin(async move { !self.router.matches_async(event).await })
+    }
+
+    fn is_async(&self) -> bool {
+        self.router.is_async()
+    }
+
+    fn cost(&self) -> Cost {
+        self.router.cost()
+    }
+}
+
+impl NotRouter {
+    pub fn new(router: Box<dyn Router>) -> Self {
+        Self { router }
+    }
+}
+
+/// Router matching if exactly one of the two routers matches, it handles no
+/// commands as the matching router may be the rejected one.
+pub struct XorRouter {
+    pub left: Box<dyn Router>,
+    pub right: Box<dyn Router>,
+}
+
+impl Router for XorRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.left.matches(event) != self.right.matches(event)
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            self.left.matches_async(event).await != self.right.matches_async(event).await
+        })
+    }
+
+    fn is_async(&self) -> bool {
+        self.left.is_async() || self.right.is_async()
+    }
+
+    fn cost(&self) -> Cost {
+        self.left.cost().max(self.right.cost())
+    }
+}
+
+impl XorRouter {
+    pub fn new(left: Box<dyn Router>, right: Box<dyn Router>) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Router for Box<dyn Router> {
+    fn matches(&self, event: &dyn Event) -> bool {
+        self.as_ref().matches(event)
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        self.as_ref().matches_async(event)
+    }
+
+    fn is_async(&self) -> bool {
+        self.as_ref().is_async()
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        self.as_ref().descriptor()
+    }
+
+    fn cost(&self) -> Cost {
+        self.as_ref().cost()
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.as_ref().commands()
+    }
+}
+
+/// Combinators for routers.
+pub trait RouterExt: Router + Sized + 'static {
+    fn boxed(self) -> Box<dyn Router> {
+        Box::new(self)
+    }
+
+    fn and<R: Router + 'static>(self, other: R) -> AndRouter {
+        AndRouter::new(vec![self.boxed(), Box::new(other)])
+    }
+
+    fn or<R: Router + 'static>(self, other: R) -> AnyRouter {
+        AnyRouter::new(vec![self.boxed(), Box::new(other)])
+    }
+
+    fn xor<R: Router + 'static>(self, other: R) -> XorRouter {
+        XorRouter::new(self.boxed(), Box::new(other))
+    }
+
+    fn not(self) -> NotRouter {
+        NotRouter::new(self.boxed())
+    }
+}
+
+impl<T: Router + Sized + 'static> RouterExt for T {}
+
+impl BitAnd for Box<dyn Router> {
+    type Output = Box<dyn Router>;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Box::new(AndRouter::new(vec![self, rhs]))
+    }
+}
+
+impl BitOr for Box<dyn Router> {
+    type Output = Box<dyn Router>;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Box::new(AnyRouter::new(vec![self, rhs]))
+    }
+}
+
+impl BitXor for Box<dyn Router> {
+    type Output = Box<dyn Router>;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Box::new(XorRouter::new(self, rhs))
+    }
+}
+
+impl Not for Box<dyn Router> {
+    type Output = Box<dyn Router>;
+
+    fn not(self) -> Self::Output {
+        Box::new(NotRouter::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    struct CountingRouter {
+        result: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl CountingRouter {
+        fn new(result: bool) -> (Self, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            (
+                Self {
+                    result,
+                    calls: calls.clone(),
+                },
+                calls,
+            )
+        }
+    }
+
+    impl Router for CountingRouter {
+        fn matches(&self, _event: &dyn Event) -> bool {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.result
+        }
+    }
+
+    struct ExpensiveRouter(CountingRouter);
+
+    impl Router for ExpensiveRouter {
+        fn matches(&self, event: &dyn Event) -> bool {
+            self.0.matches(event)
+        }
+
+        fn cost(&self) -> Cost {
+            Cost::Expensive
+        }
+    }
+
+    #[test]
+    fn test_logic_routers() {
+        let event = "hello".to_string();
+        assert!("hello".and(AllRouter).matches(&event));
+        assert!(!"hello".and("world").matches(&event));
+        assert!("world".or("hello").matches(&event));
+        assert!(!"world".or("earth").matches(&event));
+        assert!("world".not().matches(&event));
+        assert!(!"hello".not().matches(&event));
+        assert!("hello".xor("world").matches(&event));
+        assert!(!"hello".xor(AllRouter).matches(&event));
+        assert!(!"world".xor("earth").matches(&event));
+    }
+
+    #[test]
+    fn test_router_operators() {
+        let event = "hello".to_string();
+        assert!(("hello".boxed() & AllRouter.boxed()).matches(&event));
+        assert!(!("hello".boxed() & "world".boxed()).matches(&event));
+        assert!(("world".boxed() | "hello".boxed()).matches(&event));
+        assert!((!"world".boxed()).matches(&event));
+        assert!(("hello".boxed() ^ "world".boxed()).matches(&event));
+        assert!((!("world".boxed() | "earth".boxed()) & "hello".boxed()).matches(&event));
+    }
+
+    #[test]
+    fn test_and_router_short_circuit() {
+        let event = "hello".to_string();
+        let (first, first_calls) = CountingRouter::new(false);
+        let (second, second_calls) = CountingRouter::new(true);
+        assert!(!first.and(second).matches(&event));
+        assert_eq!(first_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(second_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_cost_ordering() {
+        let event = "hello".to_string();
+        let (expensive, expensive_calls) = CountingRouter::new(true);
+        let (cheap, cheap_calls) = CountingRouter::new(true);
+        let router = ExpensiveRouter(expensive).or(cheap);
+        assert_eq!(router.cost(), Cost::Expensive);
+        assert!(router.matches(&event));
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(expensive_calls.load(Ordering::SeqCst), 0);
+
+        let (expensive, expensive_calls) = CountingRouter::new(true);
+        let (cheap, cheap_calls) = CountingRouter::new(false);
+        assert!(!ExpensiveRouter(expensive).and(cheap).matches(&event));
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(expensive_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_commands_forwarded() {
+        use crate::router::{Async, AsyncRouter, CommandRouter};
+
+        struct AsyncCommand(CommandRouter);
+
+        impl AsyncRouter for AsyncCommand {
+            fn matches<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+                Box::pin(async move { self.0.matches(event) })
+            }
+
+            fn commands(&self) -> Vec<CommandSet<'_>> {
+                self.0.commands()
+            }
+        }
+
+        let names = |router: &dyn Router| {
+            router
+                .commands()
+                .into_iter()
+                .flat_map(|set| set.names)
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let help = || CommandRouter::command(["help"]);
+        // Negated routers do not handle the commands they reject.
+        assert!(names(&help().not()).is_empty());
+        assert!(names(&help().xor(CommandRouter::command(["ping"]))).is_empty());
+        assert_eq!(names(&help().and(AllRouter)), ["help"]);
+        assert_eq!(
+            names(&Async::new(AsyncCommand(help())).or(AllRouter)),
+            ["help"]
+        );
     }
 }
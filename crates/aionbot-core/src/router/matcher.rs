@@ -1,12 +1,32 @@
-use crate::event::Event;
+use std::{any::Any, sync::RwLock};
 
-use super::Router;
+use aho_corasick::{AhoCorasick, Anchored, Input, StartKind};
+
+use crate::event::{Content, Event};
+
+use super::{Cost, Descriptor, MatchContext, MatchOptions, Router};
+
+/// Compare text with options, falls back to the options of the handler.
+fn compare(
+    options: Option<MatchOptions>,
+    text: &str,
+    pattern: &str,
+    predicate: impl Fn(&str, &str) -> bool,
+) -> bool {
+    let options = options.unwrap_or_else(|| MatchContext::current().options);
+    if options.is_raw() {
+        predicate(text, pattern)
+    } else {
+        predicate(&options.normalize(text), &options.normalize(pattern))
+    }
+}
 
 pub struct ExactMatchRouter<T>
 where
     T: Send + Sync + PartialEq + 'static,
 {
     pub pattern: T,
+    pub options: Option<MatchOptions>,
 }
 
 impl<T> Router for ExactMatchRouter<T>
@@ -14,10 +34,30 @@ where
     T: Send + Sync + PartialEq + 'static,
 {
     fn matches(&self, event: &dyn Event) -> bool {
-        if let Ok(val) = event.content().downcast::<T>() {
-            *val == self.pattern
+        match event.content() {
+            Some(Content::Text(text)) => {
+                let pattern = &self.pattern as &dyn Any;
+                if let Some(pattern) = pattern.downcast_ref::<&str>() {
+                    compare(self.options, text, pattern, |a, b| a == b)
+                } else if let Some(pattern) = pattern.downcast_ref::<String>() {
+                    compare(self.options, text, pattern, |a, b| a == b)
+                } else {
+                    false
+                }
+            }
+            Some(Content::Any(content)) => content.downcast_ref::<T>() == Some(&self.pattern),
+            None => false,
+        }
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        let pattern = &self.pattern as &dyn Any;
+        if let Some(pattern) = pattern.downcast_ref::<&str>() {
+            Descriptor::exact([*pattern]).options(self.options)
+        } else if let Some(pattern) = pattern.downcast_ref::<String>() {
+            Descriptor::exact([pattern.as_str()]).options(self.options)
         } else {
-            false
+            Descriptor::Opaque
         }
     }
 }
@@ -27,7 +67,16 @@ where
     T: Send + Sync + PartialEq + 'static,
 {
     pub fn new(pattern: T) -> Self {
-        Self { pattern }
+        Self {
+            pattern,
+            options: None,
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.options = Some(options);
+        self
     }
 }
 
@@ -36,16 +85,26 @@ where
     T: Send + Sync + AsRef<str> + 'static,
 {
     pub pattern: T,
+    pub options: Option<MatchOptions>,
 }
 
-impl Router for StartsWithRouter<&str> {
+impl<T> Router for StartsWithRouter<T>
+where
+    T: Send + Sync + AsRef<str> + 'static,
+{
     fn matches(&self, event: &dyn Event) -> bool {
-        if let Ok(val) = event.content().downcast::<&str>() {
-            val.starts_with(self.pattern)
+        if let Some(val) = event.plain_text() {
+            compare(self.options, val, self.pattern.as_ref(), |a, b| {
+                a.starts_with(b)
+            })
         } else {
             false
         }
     }
+
+    fn descriptor(&self) -> Descriptor {
+        Descriptor::prefix([self.pattern.as_ref()]).options(self.options)
+    }
 }
 
 impl<T> StartsWithRouter<T>
@@ -53,7 +112,16 @@ where
     T: Send + Sync + AsRef<str> + 'static,
 {
     pub fn new(pattern: T) -> Self {
-        Self { pattern }
+        Self {
+            pattern,
+            options: None,
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.options = Some(options);
+        self
     }
 }
 
@@ -62,16 +130,26 @@ where
     T: Send + Sync + AsRef<str> + 'static,
 {
     pub pattern: T,
+    pub options: Option<MatchOptions>,
 }
 
-impl Router for ContainsRouter<&str> {
+impl<T> Router for ContainsRouter<T>
+where
+    T: Send + Sync + AsRef<str> + 'static,
+{
     fn matches(&self, event: &dyn Event) -> bool {
-        if let Ok(val) = event.content().downcast::<&str>() {
-            val.contains(self.pattern)
+        if let Some(val) = event.plain_text() {
+            compare(self.options, val, self.pattern.as_ref(), |a, b| {
+                a.contains(b)
+            })
         } else {
             false
         }
     }
+
+    fn cost(&self) -> Cost {
+        Cost::Moderate
+    }
 }
 
 impl<T> ContainsRouter<T>
@@ -79,7 +157,16 @@ where
     T: Send + Sync + AsRef<str> + 'static,
 {
     pub fn new(pattern: T) -> Self {
-        Self { pattern }
+        Self {
+            pattern,
+            options: None,
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.options = Some(options);
+        self
     }
 }
 
@@ -88,12 +175,18 @@ where
     T: Send + Sync + AsRef<str> + 'static,
 {
     pub pattern: T,
+    pub options: Option<MatchOptions>,
 }
 
-impl Router for EndsWithRouter<&str> {
+impl<T> Router for EndsWithRouter<T>
+where
+    T: Send + Sync + AsRef<str> + 'static,
+{
     fn matches(&self, event: &dyn Event) -> bool {
-        if let Ok(val) = event.content().downcast::<&str>() {
-            val.ends_with(self.pattern)
+        if let Some(val) = event.plain_text() {
+            compare(self.options, val, self.pattern.as_ref(), |a, b| {
+                a.ends_with(b)
+            })
         } else {
             false
         }
@@ -105,6 +198,315 @@ where
     T: Send + Sync + AsRef<str> + 'static,
 {
     pub fn new(pattern: T) -> Self {
-        Self { pattern }
+        Self {
+            pattern,
+            options: None,
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+}
+
+/// Automata compiled from a pattern set with resolved match options.
+struct Automata {
+    options: MatchOptions,
+    forward: AhoCorasick,
+    /// Automaton of reversed patterns, used for suffix matching.
+    reversed: AhoCorasick,
+}
+
+/// Set of patterns matched at once with Aho-Corasick automata.
+///
+/// The automata are compiled on first use and rebuilt if the resolved match
+/// options change, e.g. when the router is evaluated by another handler.
+struct PatternSet {
+    patterns: Vec<String>,
+    options: Option<MatchOptions>,
+    automata: RwLock<Option<Automata>>,
+}
+
+impl PatternSet {
+    fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|p| p.as_ref().to_string())
+                .collect(),
+            options: None,
+            automata: RwLock::new(None),
+        }
+    }
+
+    fn set_options(&mut self, options: MatchOptions) {
+        self.options = Some(options);
+        *self.automata.get_mut().unwrap() = None;
+    }
+
+    fn build(&self, options: MatchOptions) -> Result<Automata, aho_corasick::BuildError> {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|p| options.normalize(p).into_owned())
+            .collect::<Vec<_>>();
+        let reversed = patterns
+            .iter()
+            .map(|p| p.chars().rev().collect::<String>())
+            .collect::<Vec<_>>();
+        Ok(Automata {
+            options,
+            forward: AhoCorasick::builder()
+                .start_kind(StartKind::Both)
+                .build(patterns)?,
+            reversed: AhoCorasick::builder()
+                .start_kind(StartKind::Anchored)
+                .build(reversed)?,
+        })
+    }
+
+    /// Run the predicate on the automata and the normalized text.
+    fn search(&self, text: &str, predicate: impl Fn(&Automata, &str) -> bool) -> bool {
+        let options = self
+            .options
+            .unwrap_or_else(|| MatchContext::current().options);
+        if let Some(automata) = self.automata.read().unwrap().as_ref() {
+            if automata.options == options {
+                return predicate(automata, &options.normalize(text));
+            }
+        }
+        let mut cache = self.automata.write().unwrap();
+        match self.build(options) {
+            Ok(automata) => predicate(cache.insert(automata), &options.normalize(text)),
+            Err(e) => {
+                log::error!("Failed to build pattern automaton: {}", e);
+                false
+            }
+        }
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        self.search(text, |automata, text| {
+            automata
+                .forward
+                .is_match(Input::new(text).anchored(Anchored::Yes))
+        })
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.search(text, |automata, text| automata.forward.is_match(text))
+    }
+
+    fn ends_with(&self, text: &str) -> bool {
+        self.search(text, |automata, text| {
+            let text = text.chars().rev().collect::<String>();
+            automata
+                .reversed
+                .is_match(Input::new(&text).anchored(Anchored::Yes))
+        })
+    }
+}
+
+/// Router matching if the text starts with any of the patterns.
+pub struct StartsWithAnyRouter {
+    patterns: PatternSet,
+}
+
+impl Router for StartsWithAnyRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        if let Some(val) = event.plain_text() {
+            self.patterns.starts_with(val)
+        } else {
+            false
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Moderate
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        Descriptor::prefix(&self.patterns.patterns).options(self.patterns.options)
+    }
+}
+
+impl StartsWithAnyRouter {
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        Self {
+            patterns: PatternSet::new(patterns),
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.patterns.set_options(options);
+        self
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns.patterns
+    }
+}
+
+/// Router matching if the text contains any of the patterns.
+pub struct ContainsAnyRouter {
+    patterns: PatternSet,
+}
+
+impl Router for ContainsAnyRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        if let Some(val) = event.plain_text() {
+            self.patterns.contains(val)
+        } else {
+            false
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Moderate
+    }
+}
+
+impl ContainsAnyRouter {
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        Self {
+            patterns: PatternSet::new(patterns),
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.patterns.set_options(options);
+        self
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns.patterns
+    }
+}
+
+/// Router matching if the text ends with any of the patterns.
+pub struct EndsWithAnyRouter {
+    patterns: PatternSet,
+}
+
+impl Router for EndsWithAnyRouter {
+    fn matches(&self, event: &dyn Event) -> bool {
+        if let Some(val) = event.plain_text() {
+            self.patterns.ends_with(val)
+        } else {
+            false
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Moderate
+    }
+}
+
+impl EndsWithAnyRouter {
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        Self {
+            patterns: PatternSet::new(patterns),
+        }
+    }
+
+    /// Override the match options of the handler for this router.
+    pub fn options(mut self, options: MatchOptions) -> Self {
+        self.patterns.set_options(options);
+        self
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns.patterns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_matcher_options() {
+        let event = "  Ｈｅｌｌｏ，  World！ ".to_string();
+        assert!(!StartsWithRouter::new("hello").matches(&event));
+        let options = MatchOptions::loose();
+        assert!(StartsWithRouter::new("hello")
+            .options(options)
+            .matches(&event));
+        assert!(ContainsRouter::new("HELLO, WORLD")
+            .options(options)
+            .matches(&event));
+        assert!(EndsWithRouter::new("world!")
+            .options(options)
+            .matches(&event));
+        assert!(ExactMatchRouter::new("hello, world!")
+            .options(options)
+            .matches(&event));
+        assert!(!ExactMatchRouter::new("hello, world!")
+            .options(MatchOptions::new().ignore_case(true))
+            .matches(&event));
+    }
+
+    #[test]
+    fn test_context_options() {
+        let event = "Hello World".to_string();
+        let router = StartsWithAnyRouter::new(["hello"]);
+        let loose = Arc::new(MatchContext::new().options(MatchOptions::loose()));
+        assert!(loose.clone().sync_scope(|| router.matches(&event)));
+        assert!(!router.matches(&event));
+        // The cached automata follow the context evaluating the router.
+        assert!(loose.sync_scope(|| router.matches(&event)));
+        assert!(!Arc::new(MatchContext::new()).sync_scope(|| router.matches(&event)));
+    }
+
+    #[test]
+    fn test_owned_pattern() {
+        let event = "hello world".to_string();
+        assert!(StartsWithRouter::new("hello".to_string()).matches(&event));
+        assert!(ContainsRouter::new("o w".to_string()).matches(&event));
+        assert!(EndsWithRouter::new(String::from("world")).matches(&event));
+        assert!(!EndsWithRouter::new(String::from("hello")).matches(&event));
+    }
+
+    #[test]
+    fn test_multi_pattern_routers() {
+        let event = "你好，世界".to_string();
+        let patterns = ["hi", "你好", "世界"];
+        assert!(StartsWithAnyRouter::new(patterns).matches(&event));
+        assert!(!StartsWithAnyRouter::new(["世界"]).matches(&event));
+        assert!(ContainsAnyRouter::new(["，", "bye"]).matches(&event));
+        assert!(!ContainsAnyRouter::new(["bye"]).matches(&event));
+        assert!(EndsWithAnyRouter::new(patterns).matches(&event));
+        assert!(!EndsWithAnyRouter::new(["你好"]).matches(&event));
+        assert!(!ContainsAnyRouter::new(Vec::<String>::new()).matches(&event));
+
+        let event = "  ＨＥＬＬＯ   World ".to_string();
+        let router = StartsWithAnyRouter::new(vec!["hello world".to_string()]);
+        assert!(!router.matches(&event));
+        assert!(router.options(MatchOptions::loose()).matches(&event));
+        assert!(EndsWithAnyRouter::new(["o world"])
+            .options(MatchOptions::loose())
+            .matches(&event));
     }
 }
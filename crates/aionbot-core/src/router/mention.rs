@@ -0,0 +1,57 @@
+use futures::future::BoxFuture;
+
+use crate::event::Event;
+
+use super::{CommandSet, Cost, Descriptor, Router};
+
+/// Router matching only events addressed to the bot.
+pub struct ToMeRouter<R: Router> {
+    pub router: R,
+}
+
+impl<R: Router> ToMeRouter<R> {
+    pub fn new(router: R) -> Self {
+        Self { router }
+    }
+}
+
+impl<R: Router> Router for ToMeRouter<R> {
+    fn matches(&self, event: &dyn Event) -> bool {
+        event.is_to_me() && self.router.matches(event)
+    }
+
+    fn matches_async<'a>(&'a self, event: &'a dyn Event) -> BoxFuture<'a, bool> {
+        Box::pin(async move { event.is_to_me() && self.router.matches_async(event).await })
+    }
+
+    fn is_async(&self) -> bool {
+        self.router.is_async()
+    }
+
+    fn descriptor(&self) -> Descriptor {
+        self.router.descriptor()
+    }
+
+    fn cost(&self) -> Cost {
+        self.router.cost()
+    }
+
+    fn commands(&self) -> Vec<CommandSet<'_>> {
+        self.router.commands()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TestEvent;
+
+    use super::*;
+
+    #[test]
+    fn test_to_me_router() {
+        let router = ToMeRouter::new("hello");
+        assert!(router.matches(&TestEvent::new().text("hello").addressed(true)));
+        assert!(!router.matches(&TestEvent::new().text("hello").addressed(false)));
+        assert!(!router.matches(&TestEvent::new().text("hi").addressed(true)));
+    }
+}
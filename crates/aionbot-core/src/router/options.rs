@@ -0,0 +1,123 @@
+use std::borrow::Cow;
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Options to normalize text before matching.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MatchOptions {
+    /// Compare text case-insensitively.
+    pub ignore_case: bool,
+    /// Ignore leading and trailing whitespace.
+    pub trim: bool,
+    /// Treat consecutive whitespace as a single space.
+    pub collapse_whitespace: bool,
+    /// Apply Unicode NFKC normalization, e.g. full-width to half-width.
+    pub nfkc: bool,
+}
+
+impl MatchOptions {
+    /// Options matching raw text as is.
+    pub const fn new() -> Self {
+        Self {
+            ignore_case: false,
+            trim: false,
+            collapse_whitespace: false,
+            nfkc: false,
+        }
+    }
+
+    /// Options enabling all normalizations.
+    pub const fn loose() -> Self {
+        Self {
+            ignore_case: true,
+            trim: true,
+            collapse_whitespace: true,
+            nfkc: true,
+        }
+    }
+
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    pub fn collapse_whitespace(mut self, collapse_whitespace: bool) -> Self {
+        self.collapse_whitespace = collapse_whitespace;
+        self
+    }
+
+    pub fn nfkc(mut self, nfkc: bool) -> Self {
+        self.nfkc = nfkc;
+        self
+    }
+
+    /// Check whether the options leave text untouched.
+    pub fn is_raw(&self) -> bool {
+        *self == Self::new()
+    }
+
+    /// Normalize text according to the options.
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if self.nfkc {
+            text = Cow::Owned(text.nfkc().collect());
+        }
+        if self.ignore_case {
+            text = Cow::Owned(text.to_lowercase());
+        }
+        if self.collapse_whitespace {
+            let mut collapsed = String::with_capacity(text.len());
+            let mut whitespace = false;
+            for c in text.chars() {
+                if c.is_whitespace() {
+                    if !whitespace {
+                        collapsed.push(' ');
+                    }
+                    whitespace = true;
+                } else {
+                    collapsed.push(c);
+                    whitespace = false;
+                }
+            }
+            text = Cow::Owned(collapsed);
+        }
+        if self.trim {
+            text = match text {
+                Cow::Borrowed(text) => Cow::Borrowed(text.trim()),
+                Cow::Owned(text) => Cow::Owned(text.trim().to_string()),
+            };
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let text = "  Ｈｅｌｌｏ，\t  World！ ";
+        assert_eq!(MatchOptions::new().normalize(text), text);
+        assert_eq!(
+            MatchOptions::new().trim(true).normalize(text),
+            "Ｈｅｌｌｏ，\t  World！"
+        );
+        assert_eq!(
+            MatchOptions::new().nfkc(true).normalize(text),
+            "  Hello,\t  World! "
+        );
+        assert_eq!(
+            MatchOptions::new()
+                .collapse_whitespace(true)
+                .normalize(text),
+            " Ｈｅｌｌｏ， World！ "
+        );
+        assert_eq!(MatchOptions::loose().normalize(text), "hello, world!");
+    }
+}
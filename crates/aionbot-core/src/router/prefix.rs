@@ -0,0 +1,114 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Command prefixes used by command routers without their own prefixes, kept
+/// in the [`MatchContext`](super::MatchContext) of the handler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandPrefixes {
+    pub default: Vec<String>,
+    /// Prefixes overriding the default in the given channels.
+    pub channels: ChannelPrefixes,
+}
+
+impl Default for CommandPrefixes {
+    fn default() -> Self {
+        Self::new(["/"])
+    }
+}
+
+impl CommandPrefixes {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(prefixes: I) -> Self {
+        Self {
+            default: prefixes.into_iter().map(Into::into).collect(),
+            channels: ChannelPrefixes::default(),
+        }
+    }
+
+    /// Override the prefixes in a channel.
+    pub fn channel<C, S, I>(self, channel_id: C, prefixes: I) -> Self
+    where
+        C: Into<String>,
+        S: Into<String>,
+        I: IntoIterator<Item = S>,
+    {
+        self.channels.set(channel_id, prefixes);
+        self
+    }
+
+    /// Get the prefixes in the channel, falls back to the default prefixes.
+    pub fn resolve(&self, channel_id: Option<&str>) -> Cow<'_, [String]> {
+        match channel_id.and_then(|id| self.channels.get(id)) {
+            Some(prefixes) => Cow::Owned(prefixes),
+            None => Cow::Borrowed(&self.default),
+        }
+    }
+}
+
+/// Prefix overrides of channels which may be changed while the bot is
+/// running, clones share the same overrides.
+///
+/// The builder keeps the overrides of the handler in the state, so they can
+/// be changed with `manager.get::<ChannelPrefixes>()`. Entries are not
+/// indexed by overridden prefixes, messages in those channels are evaluated
+/// against every entry.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelPrefixes(Arc<RwLock<HashMap<String, Vec<String>>>>);
+
+impl ChannelPrefixes {
+    /// Override the prefixes in a channel.
+    pub fn set<C, S, I>(&self, channel_id: C, prefixes: I)
+    where
+        C: Into<String>,
+        S: Into<String>,
+        I: IntoIterator<Item = S>,
+    {
+        let prefixes = prefixes.into_iter().map(Into::into).collect();
+        self.0.write().unwrap().insert(channel_id.into(), prefixes);
+    }
+
+    /// Remove the override of a channel, returns the removed prefixes.
+    pub fn remove(&self, channel_id: &str) -> Option<Vec<String>> {
+        self.0.write().unwrap().remove(channel_id)
+    }
+
+    /// Get the prefixes overriding the default in the channel.
+    pub fn get(&self, channel_id: &str) -> Option<Vec<String>> {
+        self.0.read().unwrap().get(channel_id).cloned()
+    }
+
+    /// Check if the prefixes in the channel are overridden.
+    pub fn contains(&self, channel_id: &str) -> bool {
+        self.0.read().unwrap().contains_key(channel_id)
+    }
+}
+
+impl PartialEq for ChannelPrefixes {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || *self.0.read().unwrap() == *other.0.read().unwrap()
+    }
+}
+
+impl Eq for ChannelPrefixes {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_prefixes() {
+        let prefixes = CommandPrefixes::new(["/", "!"]).channel("10001", ["#"]);
+        assert_eq!(*prefixes.resolve(None), ["/", "!"]);
+        assert_eq!(*prefixes.resolve(Some("10002")), ["/", "!"]);
+        assert_eq!(*prefixes.resolve(Some("10001")), ["#"]);
+
+        // Clones share the overrides.
+        let channels = prefixes.clone().channels;
+        channels.set("10002", ["~"]);
+        assert_eq!(*prefixes.resolve(Some("10002")), ["~"]);
+        assert_eq!(channels.remove("10001"), Some(vec!["#".to_string()]));
+        assert_eq!(*prefixes.resolve(Some("10001")), ["/", "!"]);
+    }
+}
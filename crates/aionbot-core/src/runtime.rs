@@ -3,7 +3,16 @@ use std::{cell::UnsafeCell, sync::Arc};
 use anyhow::Result;
 use state::TypeMap;
 
-use crate::{entry::Entry, event::Event, handler::Handler, plugin::AionPlugin, types::SetupFn};
+use crate::{
+    command::CommandGroup,
+    entry::Entry,
+    event::Event,
+    flow::Flow,
+    handler::Handler,
+    plugin::AionPlugin,
+    router::{ChannelPrefixes, MatchOptions},
+    types::SetupFn,
+};
 
 #[derive(Default)]
 pub struct StateManager(pub(crate) TypeMap!(Send + Sync));
@@ -50,6 +59,38 @@ where
         self
     }
 
+    /// Register a command group with its sub-commands.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a command group with the same name is already registered.
+    pub fn command(mut self, group: CommandGroup) -> Self {
+        let name = group.name().to_string();
+        let entry = group.into_entry();
+        if self
+            .handler
+            .get_mut()
+            .entries()
+            .iter()
+            .any(|e| e.id == entry.id)
+        {
+            panic!("Command group [{}] is already registered", name);
+        }
+        self.invoke_handler([entry])
+    }
+
+    /// Register a conversation flow, its progress is kept in the state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a flow with the same name is already registered.
+    pub fn flow(self, flow: Flow) -> Self {
+        let entry = flow
+            .into_entry(self.state.clone())
+            .unwrap_or_else(|e| panic!("{}", e));
+        self.invoke_handler([entry])
+    }
+
     pub fn plugin(self, plugin: AionPlugin) -> Self {
         self.invoke_handler(plugin.entries().to_vec())
     }
@@ -59,6 +100,46 @@ where
         self
     }
 
+    /// Set the default text match options for routers without their own.
+    pub fn match_options(mut self, options: MatchOptions) -> Self {
+        self.handler
+            .get_mut()
+            .update_context(|context| context.options = options);
+        self
+    }
+
+    /// Reply suggestions of similar commands to unknown commands.
+    pub fn suggest_commands(mut self, enabled: bool) -> Self {
+        self.handler.get_mut().suggest_commands(enabled);
+        self
+    }
+
+    /// Set the default command prefixes for routers without their own.
+    pub fn command_prefixes<S: Into<String>, I: IntoIterator<Item = S>>(
+        mut self,
+        prefixes: I,
+    ) -> Self {
+        let prefixes = prefixes.into_iter().map(Into::into).collect();
+        self.handler
+            .get_mut()
+            .update_context(|context| context.prefixes.default = prefixes);
+        self
+    }
+
+    /// Override the command prefixes in a channel, the overrides can be
+    /// changed later with the [`ChannelPrefixes`] kept in the state.
+    pub fn channel_prefixes<C, S, I>(self, channel_id: C, prefixes: I) -> Self
+    where
+        C: Into<String>,
+        S: Into<String>,
+        I: IntoIterator<Item = S>,
+    {
+        self.state
+            .get::<ChannelPrefixes>()
+            .set(channel_id, prefixes);
+        self
+    }
+
     async fn prepare(&mut self) -> Result<()> {
         log::debug!("Preparing for runtime...");
         self.runtime.prepare().await?;
@@ -103,8 +184,10 @@ where
     fn default() -> Self {
         let manager = Arc::new(StateManager::new());
         let runtime = R::default().set_manager(manager.clone());
+        let handler = Handler::empty();
+        manager.set(handler.context().prefixes.channels.clone());
         Self {
-            handler: UnsafeCell::new(Handler::empty()),
+            handler: UnsafeCell::new(handler),
             runtime,
             state: Arc::clone(&manager),
             setup: None,
@@ -139,3 +222,60 @@ pub enum RuntimeStatus {
     Restart,
     Event(Box<dyn Event>),
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TestEvent;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct TestRuntime {
+        manager: Arc<StateManager>,
+    }
+
+    impl Runtime for TestRuntime {
+        fn set_manager(self, manager: Arc<StateManager>) -> Self {
+            Self { manager }
+        }
+
+        fn manager(&self) -> &StateManager {
+            &self.manager
+        }
+
+        async fn run(&mut self) -> Result<RuntimeStatus> {
+            Ok(RuntimeStatus::Exit)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_channel_prefixes() {
+        let builder = Builder::<TestRuntime>::default()
+            .command(CommandGroup::new("ping"))
+            .channel_prefixes("20001", ["#"]);
+        let handler = unsafe { &*builder.handler.get() };
+        let matched = |text: &str| {
+            let event = TestEvent::new().group("20001").text(text).boxed();
+            async move { handler.matches(event).await.pop().is_some() }
+        };
+        assert!(matched("#ping").await);
+        assert!(!matched("/ping").await);
+
+        let prefixes = builder.runtime.manager().get::<ChannelPrefixes>();
+        prefixes.set("20001", ["~"]);
+        assert!(matched("~ping").await);
+        assert!(!matched("#ping").await);
+        prefixes.remove("20001");
+        assert!(matched("/ping").await);
+        assert!(!matched("~ping").await);
+    }
+
+    #[test]
+    #[should_panic(expected = "Command group [config] is already registered")]
+    fn test_duplicate_command_group() {
+        let _ = Builder::<TestRuntime>::default()
+            .command(CommandGroup::new("config"))
+            .command(CommandGroup::new("ping"))
+            .command(CommandGroup::new("config"));
+    }
+}
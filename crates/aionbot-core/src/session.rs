@@ -0,0 +1,266 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+
+use crate::{event::Event, identity::Channel, router::Router};
+
+tokio::task_local! {
+    static SESSIONS: Arc<Sessions>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    /// No matching event arrived in time.
+    Timeout,
+    /// Waiting outside of a handler, where no session registry is available.
+    Unavailable,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("session timed out"),
+            Self::Unavailable => f.write_str("sessions are only available in handlers"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Filter of the events a session waits for.
+#[derive(Default)]
+pub struct SessionFilter {
+    pub user_id: Option<String>,
+    pub channel: Option<Channel>,
+    pub router: Option<Box<dyn Router>>,
+}
+
+impl SessionFilter {
+    /// Match any event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match events from the same sender in the same channel as the event,
+    /// private conversations only match the same private channel.
+    pub fn conversation(event: &dyn Event) -> Self {
+        Self {
+            user_id: event.emitter_id().map(String::from),
+            channel: event.channel().cloned(),
+            router: None,
+        }
+    }
+
+    pub fn user<S: Into<String>>(mut self, user_id: S) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Only match events matched by the router.
+    pub fn router<R: Router + 'static>(mut self, router: R) -> Self {
+        self.router = Some(Box::new(router));
+        self
+    }
+
+    pub async fn matches(&self, event: &dyn Event) -> bool {
+        let matches = self
+            .user_id
+            .as_deref()
+            .is_none_or(|id| event.emitter_id() == Some(id))
+            && self
+                .channel
+                .as_ref()
+                .is_none_or(|channel| event.channel() == Some(channel));
+        match &self.router {
+            Some(router) if matches => router.matches_async(event).await,
+            _ => matches,
+        }
+    }
+}
+
+struct Waiter {
+    id: u64,
+    filter: Arc<SessionFilter>,
+    sender: oneshot::Sender<Arc<Box<dyn Event>>>,
+}
+
+/// Registry of sessions waiting for events.
+#[derive(Default)]
+pub struct Sessions {
+    waiters: Mutex<Vec<Waiter>>,
+    counter: AtomicU64,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deliver the event to the earliest waiting session it matches, returns
+    /// `false` if no session took it.
+    pub async fn deliver(&self, event: &Arc<Box<dyn Event>>) -> bool {
+        let filters = {
+            let mut waiters = self.waiters.lock().unwrap();
+            waiters.retain(|waiter| !waiter.sender.is_closed());
+            waiters
+                .iter()
+                .map(|waiter| (waiter.id, waiter.filter.clone()))
+                .collect::<Vec<_>>()
+        };
+        // Routers may await, so the filters are matched without the lock held.
+        for (id, filter) in filters {
+            if !filter.matches(&***event).await {
+                continue;
+            }
+            let mut waiters = self.waiters.lock().unwrap();
+            let Some(i) = waiters.iter().position(|waiter| waiter.id == id) else {
+                continue;
+            };
+            if waiters.remove(i).sender.send(event.clone()).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Wait for the next event matching the filter.
+    pub async fn wait(
+        &self,
+        filter: SessionFilter,
+        timeout: Duration,
+    ) -> Result<Arc<Box<dyn Event>>, SessionError> {
+        let id = self.counter.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.lock().unwrap().push(Waiter {
+            id,
+            filter: Arc::new(filter),
+            sender,
+        });
+        let result = tokio::time::timeout(timeout, receiver).await;
+        self.waiters
+            .lock()
+            .unwrap()
+            .retain(|waiter| waiter.id != id);
+        match result {
+            Ok(Ok(event)) => Ok(event),
+            _ => Err(SessionError::Timeout),
+        }
+    }
+
+    /// Number of sessions waiting for events.
+    pub fn len(&self) -> usize {
+        self.waiters.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run the future with the registry available to [`wait_for`].
+    pub(crate) fn scope<F: Future>(self: Arc<Self>, future: F) -> impl Future<Output = F::Output> {
+        SESSIONS.scope(self, future)
+    }
+}
+
+/// Wait in a handler for the next event matching the filter, the event is
+/// delivered to the session instead of being dispatched to handlers.
+pub async fn wait_for(
+    filter: SessionFilter,
+    timeout: Duration,
+) -> Result<Arc<Box<dyn Event>>, SessionError> {
+    let sessions = SESSIONS
+        .try_with(Arc::clone)
+        .map_err(|_| SessionError::Unavailable)?;
+    sessions.wait(filter, timeout).await
+}
+
+/// Wait in a handler for the next event from the same sender in the same
+/// channel as the event.
+pub async fn wait_reply(
+    event: &dyn Event,
+    timeout: Duration,
+) -> Result<Arc<Box<dyn Event>>, SessionError> {
+    wait_for(SessionFilter::conversation(event), timeout).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{entry::Entry, handler::Handler, testing::TestEvent};
+
+    use super::*;
+
+    fn text(text: &str) -> Arc<Box<dyn Event>> {
+        Arc::new(Box::new(text.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_wait_reply() {
+        let answers = Arc::new(Mutex::new(vec![]));
+        let recorded = answers.clone();
+        let handler = Arc::new(Handler::new(vec![Entry {
+            id: "ask",
+            priority: 0,
+            router: Arc::new(Box::new("ask")),
+            callback: Arc::new(move |event| {
+                let recorded = recorded.clone();
+                Box::pin(async move {
+                    let answer = wait_reply(&**event, Duration::from_secs(5)).await?;
+                    let answer = answer.plain_text().unwrap_or_default().to_string();
+                    recorded.lock().unwrap().push(answer);
+                    Ok(())
+                })
+            }),
+        }]));
+
+        let task = tokio::spawn({
+            let handler = handler.clone();
+            async move { handler.input(text("ask")).await }
+        });
+        while handler.sessions().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        // Delivered to the session instead of asking again.
+        handler.input(text("ask")).await.unwrap();
+        task.await.unwrap().unwrap();
+        assert_eq!(*answers.lock().unwrap(), ["ask"]);
+        assert!(handler.sessions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_filter() {
+        let private = TestEvent::new().user("10001");
+        let group = TestEvent::new().user("10001").group("20001");
+        let filter = SessionFilter::conversation(&private);
+        assert!(filter.matches(&private).await);
+        assert!(!filter.matches(&group).await);
+        let filter = SessionFilter::conversation(&group);
+        assert!(filter.matches(&group).await);
+        assert!(!filter.matches(&private).await);
+        assert!(!filter.matches(&"hello".to_string()).await);
+    }
+
+    #[tokio::test]
+    async fn test_session_errors() {
+        let sessions = Sessions::new();
+        let filter = SessionFilter::new().router("never");
+        let result = sessions.wait(filter, Duration::from_millis(10)).await;
+        assert_eq!(result.err(), Some(SessionError::Timeout));
+        assert!(sessions.is_empty());
+        assert!(!sessions.deliver(&text("never")).await);
+        let result = wait_for(SessionFilter::new(), Duration::from_millis(10)).await;
+        assert_eq!(result.err(), Some(SessionError::Unavailable));
+    }
+}
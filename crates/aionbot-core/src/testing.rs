@@ -0,0 +1,90 @@
+//! Event fixture shared by unit tests.
+
+use std::{any::Any, sync::Arc};
+
+use crate::{
+    event::{Content, Event},
+    identity::{Channel, User},
+};
+
+/// Message event built field by field.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TestEvent {
+    pub text: Option<String>,
+    pub sender: Option<User>,
+    pub channel: Option<Channel>,
+    pub to_me: bool,
+}
+
+impl TestEvent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text<S: Into<String>>(mut self, text: S) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    pub fn sender(mut self, sender: User) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Send the event from the user, in a private chat unless the channel
+    /// is set.
+    pub fn user(mut self, user_id: &str) -> Self {
+        self.sender = Some(User::new(user_id));
+        self.channel.get_or_insert_with(|| Channel::Private {
+            user_id: user_id.into(),
+        });
+        self
+    }
+
+    pub fn group(mut self, group_id: &str) -> Self {
+        self.channel = Some(Channel::Group {
+            group_id: group_id.into(),
+        });
+        self
+    }
+
+    /// Address the event to the bot.
+    pub fn addressed(mut self, to_me: bool) -> Self {
+        self.to_me = to_me;
+        self
+    }
+
+    pub fn boxed(self) -> Arc<Box<dyn Event>> {
+        Arc::new(Box::new(self))
+    }
+}
+
+impl Event for TestEvent {
+    fn event_type(&self) -> &str {
+        "message"
+    }
+
+    fn adapter(&self) -> &str {
+        "test"
+    }
+
+    fn content(&self) -> Option<Content<'_>> {
+        self.text.as_deref().map(Content::Text)
+    }
+
+    fn sender(&self) -> Option<&User> {
+        self.sender.as_ref()
+    }
+
+    fn channel(&self) -> Option<&Channel> {
+        self.channel.as_ref()
+    }
+
+    fn is_to_me(&self) -> bool {
+        self.to_me
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
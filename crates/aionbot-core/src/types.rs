@@ -3,8 +3,16 @@ use std::sync::Arc;
 use anyhow::Result;
 use futures::future::BoxFuture;
 
-use crate::event::Event;
+use crate::{
+    command::CommandArgs,
+    event::Event,
+    flow::{FlowContext, Transition},
+};
 
 pub type HandlerCallback = BoxFuture<'static, Result<()>>;
-pub type Callback = fn(Arc<Box<dyn Event>>) -> HandlerCallback;
+pub type Callback = dyn Fn(Arc<Box<dyn Event>>) -> HandlerCallback + Send + Sync;
+pub type CommandCallback =
+    dyn Fn(Arc<Box<dyn Event>>, CommandArgs) -> HandlerCallback + Send + Sync;
+pub type FlowFuture = BoxFuture<'static, Result<Transition>>;
+pub type FlowCallback = dyn Fn(Arc<Box<dyn Event>>, FlowContext) -> FlowFuture + Send + Sync;
 pub type SetupFn<R> = Box<dyn FnOnce(&R) + Send + Sync>;
@@ -7,6 +7,9 @@ use syn::{meta::ParseNestedMeta, parse_macro_input, Result};
 struct HandlerArgs {
     priority: syn::LitInt,
     router: Option<syn::Expr>,
+    rate_limits: Vec<syn::Expr>,
+    cooldown: Option<syn::LitInt>,
+    limited: Option<syn::LitStr>,
 }
 
 impl Default for HandlerArgs {
@@ -14,6 +17,9 @@ impl Default for HandlerArgs {
         Self {
             priority: syn::LitInt::new("0", proc_macro2::Span::call_site()),
             router: None,
+            rate_limits: vec![],
+            cooldown: None,
+            limited: None,
         }
     }
 }
@@ -30,6 +36,18 @@ impl HandlerArgs {
                     self.priority = meta.value()?.parse()?;
                     Ok(())
                 }
+                "rate_limit" => {
+                    self.rate_limits.push(meta.value()?.parse()?);
+                    Ok(())
+                }
+                "cooldown" => {
+                    self.cooldown = Some(meta.value()?.parse()?);
+                    Ok(())
+                }
+                "limited" => {
+                    self.limited = Some(meta.value()?.parse()?);
+                    Ok(())
+                }
                 _ => Err(meta.error("msg")),
             }
         } else {
@@ -50,6 +68,23 @@ fn get_router(handler_args: &HandlerArgs) -> syn::Expr {
     }
 }
 
+/// Wrap the callback with the rate limits, `cooldown` is in seconds per user.
+fn get_callback(handler_args: &HandlerArgs, callback: &syn::Ident) -> proc_macro2::TokenStream {
+    if handler_args.rate_limits.is_empty() && handler_args.cooldown.is_none() {
+        return quote! { Arc::new(#callback) };
+    }
+    let rate_limits = &handler_args.rate_limits;
+    let cooldown = handler_args.cooldown.iter();
+    let limited = handler_args.limited.iter();
+    quote! {
+        Limited::default()
+            #(.limit(#rate_limits))*
+            #(.limit(RateLimit::cooldown(std::time::Duration::from_secs(#cooldown))))*
+            #(.message(#limited))*
+            .wrap(#callback)
+    }
+}
+
 fn get_hash_id(ident: &syn::Ident) -> String {
     let mut hasher = DefaultHasher::new();
     ident.hash(&mut hasher);
@@ -90,6 +125,7 @@ pub fn register(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     let router = get_router(&attrs);
     let priority = &attrs.priority;
+    let callback = get_callback(&attrs, &fn_name_ident);
 
     if attrs.is_empty() {
         return TokenStream::from(
@@ -111,7 +147,7 @@ pub fn register(attr: TokenStream, item: TokenStream) -> TokenStream {
                 id: #hash_id,
                 priority: #priority,
                 router: Arc::new(Box::new(#router)),
-                callback: Arc::new(#fn_name_ident),
+                callback: #callback,
             }
         }
     };
@@ -1,16 +1,23 @@
+use aionbot_core::identity::User;
 use aionbot_macros::register;
+use futures::executor::block_on;
 
 struct ConcreteEvent {
     plain_data: String,
+    sender: Option<User>,
 }
 
 impl Event for ConcreteEvent {
-    fn content(&self) -> Box<dyn std::any::Any> {
-        Box::new(self.plain_data.clone().leak() as &str)
+    fn content(&self) -> Option<Content<'_>> {
+        Some(Content::Text(&self.plain_data))
     }
 
     fn event_type(&self) -> &str {
-        unimplemented!()
+        "message"
+    }
+
+    fn sender(&self) -> Option<&User> {
+        self.sender.as_ref()
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -32,6 +39,7 @@ pub fn test_register_fn_priority(_event: Arc<Box<dyn Event>>) -> Result<()> {
 fn test_register_router() {
     let event: Box<dyn Event> = Box::new(ConcreteEvent {
         plain_data: "test_router".to_string(),
+        sender: None,
     });
     let entry = test_register_fn();
     assert!(entry.priority == 0);
@@ -42,8 +50,26 @@ fn test_register_router() {
 fn test_register_router_priority() {
     let event: Box<dyn Event> = Box::new(ConcreteEvent {
         plain_data: "test_router".to_string(),
+        sender: None,
     });
     let entry = test_register_fn_priority();
     assert!(entry.priority == 1);
     assert!(entry.router.matches(&*event));
 }
+
+#[register(router = "test_router", cooldown = 60, limited = "wait {secs}s")]
+pub fn test_register_fn_cooldown(_event: Arc<Box<dyn Event>>) -> Result<()> {
+    Ok(())
+}
+
+#[test]
+fn test_register_cooldown() {
+    let event: Arc<Box<dyn Event>> = Arc::new(Box::new(ConcreteEvent {
+        plain_data: "test_router".to_string(),
+        sender: Some(User::new("10001")),
+    }));
+    let entry = test_register_fn_cooldown();
+    assert!(block_on((entry.callback)(event.clone())).is_ok());
+    // Limited, replying the message is unsupported by the event.
+    assert!(block_on((entry.callback)(event)).is_err());
+}